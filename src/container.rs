@@ -0,0 +1,255 @@
+//! Container runtime log envelopes: Docker's json-file driver,
+//! `{"log":"...\n","stream":"stdout","time":"..."}`, and the Kubernetes CRI
+//! format, `2024-01-29T00:50:43.123456789Z stdout F ...`.
+
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// An application log line and where it came from.
+#[derive(Debug, PartialEq)]
+pub struct Envelope {
+    pub time: String,
+    pub stream: String,
+    pub log: String,
+    /// More of the line follows in the next envelope
+    pub partial: bool,
+}
+
+/// Recognises a Docker json-file record.
+pub fn docker(obj: &Map<String, Value>) -> Option<Envelope> {
+    if !obj
+        .keys()
+        .all(|k| k == "log" || k == "stream" || k == "time" || k == "attrs")
+    {
+        return None;
+    }
+    let log = obj.get("log")?.as_str()?;
+    let stream = obj.get("stream")?.as_str()?;
+    let time = obj.get("time")?.as_str()?;
+    // Docker splits long lines, leaving the newline off all but the last part
+    let partial = !log.ends_with('\n');
+    Some(Envelope {
+        time: time.to_string(),
+        stream: stream.to_string(),
+        log: log.trim_end_matches(&['\n', '\r'][..]).to_string(),
+        partial,
+    })
+}
+
+fn cri_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"^(\d{4}-\d{2}-\d{2}T\S+) (stdout|stderr) ([FP]) (.*)$").unwrap())
+}
+
+/// Recognises a CRI log line.
+pub fn cri(line: &str) -> Option<Envelope> {
+    let caps = cri_re().captures(line.trim_end_matches(&['\n', '\r'][..]))?;
+    Some(Envelope {
+        time: caps[1].to_string(),
+        stream: caps[2].to_string(),
+        log: caps[4].to_string(),
+        partial: &caps[3] == "P",
+    })
+}
+
+/// Joins lines that container runtimes split into several partial
+/// envelopes, so each application log line can be formatted whole. Parts
+/// are joined per stream, as stdout and stderr can be interleaved. Other
+/// lines pass straight through.
+#[derive(Default)]
+pub struct Reassembler {
+    pending: HashMap<String, Pending>,
+}
+
+/// The start of a line, and the last envelope it came in.
+struct Pending {
+    log: String,
+    last: Last,
+}
+
+enum Last {
+    Cri { time: String },
+    Docker(Map<String, Value>),
+}
+
+impl Pending {
+    /// The line written out in the format it came in.
+    fn into_line(self, stream: &str) -> String {
+        match self.last {
+            Last::Cri { time } => format!("{} {} F {}", time, stream, self.log),
+            Last::Docker(mut obj) => {
+                obj.insert("log".to_string(), Value::String(self.log + "\n"));
+                Value::Object(obj).to_string()
+            }
+        }
+    }
+}
+
+impl Reassembler {
+    pub fn new() -> Reassembler {
+        Reassembler::default()
+    }
+
+    /// Takes the next input line, returning a complete line if there is one.
+    pub fn push(&mut self, line: &str) -> Option<String> {
+        if let Some(env) = cri(line) {
+            let last = Last::Cri { time: env.time };
+            return self.add(env.stream, env.log, env.partial, last);
+        }
+        if line.starts_with("{\"log\":") {
+            if let Ok(Value::Object(obj)) = ::serde_json::from_str::<Value>(line) {
+                if let Some(env) = docker(&obj) {
+                    if env.partial || self.pending.contains_key(&env.stream) {
+                        return self.add(env.stream, env.log, env.partial, Last::Docker(obj));
+                    }
+                }
+            }
+        }
+        Some(line.to_string())
+    }
+
+    fn add(&mut self, stream: String, log: String, partial: bool, last: Last) -> Option<String> {
+        let mut joined = self
+            .pending
+            .remove(&stream)
+            .map_or(String::new(), |p| p.log);
+        joined.push_str(&log);
+        let pending = Pending { log: joined, last };
+        if partial {
+            self.pending.insert(stream, pending);
+            return None;
+        }
+        Some(pending.into_line(&stream))
+    }
+
+    /// Gives up waiting for the rest of a partial line, at the end of the
+    /// input, returning what there is of it. Call until it returns `None`,
+    /// as each stream may have one.
+    pub fn finish(&mut self) -> Option<String> {
+        let stream = self.pending.keys().min()?.clone();
+        let pending = self.pending.remove(&stream)?;
+        Some(pending.into_line(&stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cri, docker, Envelope, Reassembler};
+    use serde_json::Value;
+
+    #[test]
+    fn docker_envelope() {
+        let obj =
+            json!({"log": "{\"a\":1}\n", "stream": "stderr", "time": "2024-01-29T00:50:43.123Z"});
+        assert_eq!(
+            docker(obj.as_object().unwrap()),
+            Some(Envelope {
+                time: "2024-01-29T00:50:43.123Z".to_string(),
+                stream: "stderr".to_string(),
+                log: "{\"a\":1}".to_string(),
+                partial: false,
+            })
+        );
+        let obj = json!({"log": "x", "stream": "stdout", "time": "t", "level": "info"});
+        assert_eq!(docker(obj.as_object().unwrap()), None);
+    }
+
+    #[test]
+    fn cri_line() {
+        assert_eq!(
+            cri("2024-01-29T00:50:43.123456789Z stdout P {\"a\":\n"),
+            Some(Envelope {
+                time: "2024-01-29T00:50:43.123456789Z".to_string(),
+                stream: "stdout".to_string(),
+                log: "{\"a\":".to_string(),
+                partial: true,
+            })
+        );
+        assert_eq!(cri("stdout F hello"), None);
+    }
+
+    #[test]
+    fn reassemble_cri() {
+        let mut r = Reassembler::new();
+        assert_eq!(r.push("2024-01-29T00:50:43Z stdout P {\"a\":"), None);
+        assert_eq!(r.push("2024-01-29T00:50:43Z stdout P  1,"), None);
+        assert_eq!(
+            r.push("2024-01-29T00:50:44Z stdout F \"b\":2}\n"),
+            Some("2024-01-29T00:50:44Z stdout F {\"a\": 1,\"b\":2}".to_string())
+        );
+        assert_eq!(
+            r.push("2024-01-29T00:50:45Z stdout F done"),
+            Some("2024-01-29T00:50:45Z stdout F done".to_string())
+        );
+    }
+
+    #[test]
+    fn reassemble_docker() {
+        let mut r = Reassembler::new();
+        assert_eq!(
+            r.push("{\"log\":\"{\\\"a\\\":\",\"stream\":\"stdout\",\"time\":\"t1\"}"),
+            None
+        );
+        let joined = r
+            .push("{\"log\":\"1}\\n\",\"stream\":\"stdout\",\"time\":\"t2\"}\n")
+            .unwrap();
+        assert_eq!(
+            ::serde_json::from_str::<Value>(&joined).unwrap(),
+            json!({"log": "{\"a\":1}\n", "stream": "stdout", "time": "t2"})
+        );
+        assert_eq!(r.push("plain\n"), Some("plain\n".to_string()));
+    }
+
+    #[test]
+    fn reassemble_interleaved_streams() {
+        let mut r = Reassembler::new();
+        assert_eq!(r.push("2024-01-29T00:50:43Z stdout P out "), None);
+        assert_eq!(r.push("2024-01-29T00:50:43Z stderr P err "), None);
+        assert_eq!(
+            r.push("2024-01-29T00:50:44Z stdout F one"),
+            Some("2024-01-29T00:50:44Z stdout F out one".to_string())
+        );
+        assert_eq!(
+            r.push("2024-01-29T00:50:44Z stderr F two"),
+            Some("2024-01-29T00:50:44Z stderr F err two".to_string())
+        );
+
+        assert_eq!(
+            r.push("{\"log\":\"out \",\"stream\":\"stdout\",\"time\":\"t1\"}"),
+            None
+        );
+        assert_eq!(
+            r.push("{\"log\":\"err\\n\",\"stream\":\"stderr\",\"time\":\"t2\"}"),
+            Some("{\"log\":\"err\\n\",\"stream\":\"stderr\",\"time\":\"t2\"}".to_string())
+        );
+        let joined = r
+            .push("{\"log\":\"one\\n\",\"stream\":\"stdout\",\"time\":\"t3\"}")
+            .unwrap();
+        assert_eq!(
+            ::serde_json::from_str::<Value>(&joined).unwrap(),
+            json!({"log": "out one\n", "stream": "stdout", "time": "t3"})
+        );
+    }
+
+    #[test]
+    fn reassemble_finish() {
+        let mut r = Reassembler::new();
+        assert_eq!(r.finish(), None);
+        assert_eq!(r.push("2024-01-29T00:50:43Z stdout P {\"a\":"), None);
+        assert_eq!(
+            r.push("{\"log\":\"cut\",\"stream\":\"stderr\",\"time\":\"t1\"}"),
+            None
+        );
+        assert_eq!(
+            r.finish(),
+            Some("{\"log\":\"cut\\n\",\"stream\":\"stderr\",\"time\":\"t1\"}".to_string())
+        );
+        assert_eq!(
+            r.finish(),
+            Some("2024-01-29T00:50:43Z stdout F {\"a\":".to_string())
+        );
+        assert_eq!(r.finish(), None);
+    }
+}
@@ -0,0 +1,439 @@
+//! Looking up fields by dotted path, e.g. `log.level`. A path matches a key
+//! containing literal dots as well as nested objects, so `log.level` finds
+//! both `{"log.level": "info"}` and `{"log": {"level": "info"}}`.
+
+use glob::Pattern;
+use serde_json::{Map, Value};
+use std::str::FromStr;
+
+/// Finds the value at `path`, preferring an exact key match.
+pub fn lookup<'a>(obj: &'a Map<String, Value>, path: &str) -> Option<&'a Value> {
+    if let Some(v) = obj.get(path) {
+        return Some(v);
+    }
+    for (i, _) in path.match_indices('.') {
+        if let Some(Value::Object(inner)) = obj.get(&path[..i]) {
+            if let Some(v) = lookup(inner, &path[i + 1..]) {
+                return Some(v);
+            }
+        }
+    }
+    None
+}
+
+/// Removes and returns the value at `path`. Nested objects left empty by
+/// the removal are removed too.
+pub fn remove(obj: &mut Map<String, Value>, path: &str) -> Option<Value> {
+    if let Some(v) = obj.shift_remove(path) {
+        return Some(v);
+    }
+    for (i, _) in path.match_indices('.') {
+        let (key, rest) = (&path[..i], &path[i + 1..]);
+        let mut now_empty = false;
+        let mut removed = None;
+        if let Some(Value::Object(inner)) = obj.get_mut(key) {
+            removed = remove(inner, rest);
+            now_empty = inner.is_empty();
+        }
+        if removed.is_some() {
+            if now_empty {
+                obj.shift_remove(key);
+            }
+            return removed;
+        }
+    }
+    None
+}
+
+/// Parses string values holding JSON objects or arrays, such as
+/// double-encoded payloads, so they can be shown as structure. JSON found
+/// inside those is parsed too, down to `depth` levels.
+pub fn unpack_json(val: Value, depth: u32) -> Value {
+    match val {
+        Value::String(s) => {
+            let looks_like_json = s.trim_start().starts_with(['{', '[']);
+            if depth == 0 || !looks_like_json {
+                return Value::String(s);
+            }
+            match ::serde_json::from_str(&s) {
+                Ok(v @ Value::Object(_)) | Ok(v @ Value::Array(_)) => unpack_json(v, depth - 1),
+                _ => Value::String(s),
+            }
+        }
+        Value::Object(obj) => Value::Object(
+            obj.into_iter()
+                .map(|(k, v)| (k, unpack_json(v, depth)))
+                .collect(),
+        ),
+        Value::Array(arr) => Value::Array(arr.into_iter().map(|v| unpack_json(v, depth)).collect()),
+        v => v,
+    }
+}
+
+/// Replaces nested objects with their fields under dotted keys, so
+/// `{"http": {"status": 200}}` becomes `{"http.status": 200}`. Empty
+/// objects are kept as they are. When a dotted key is already taken, as in
+/// `{"a.b": 1, "a": {"b": 2}}`, the later value is kept as `a.b#2`.
+pub fn flatten(obj: Map<String, Value>) -> Map<String, Value> {
+    let mut flat = Map::new();
+    flatten_into(&mut flat, obj, "");
+    flat
+}
+
+fn flatten_into(flat: &mut Map<String, Value>, obj: Map<String, Value>, prefix: &str) {
+    for (key, val) in obj {
+        let path = format!("{}{}", prefix, key);
+        match val {
+            Value::Object(inner) if !inner.is_empty() => {
+                flatten_into(flat, inner, &format!("{}.", path))
+            }
+            val => {
+                let mut key = path.clone();
+                let mut n = 2;
+                while flat.contains_key(&key) {
+                    key = format!("{}#{}", path, n);
+                    n += 1;
+                }
+                flat.insert(key, val);
+            }
+        }
+    }
+}
+
+/// A set of dotted paths, which may contain glob patterns like `k8s.*`.
+#[derive(Clone, Debug)]
+pub struct FieldSet {
+    patterns: Vec<Pattern>,
+}
+
+impl FieldSet {
+    pub fn new(paths: &[String]) -> Result<FieldSet, String> {
+        let patterns = paths
+            .iter()
+            .map(|p| Pattern::new(p).map_err(|err| format!("invalid pattern `{}`: {}", p, err)))
+            .collect::<Result<Vec<Pattern>, String>>()?;
+        Ok(FieldSet { patterns })
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        self.patterns.iter().any(|p| p.matches(path))
+    }
+
+    /// Removes the fields in the set, along with objects left empty.
+    pub fn hide(&self, obj: &mut Map<String, Value>) {
+        self.hide_under(obj, "");
+    }
+
+    fn hide_under(&self, obj: &mut Map<String, Value>, prefix: &str) {
+        let keys: Vec<String> = obj.keys().cloned().collect();
+        for key in keys {
+            let path = format!("{}{}", prefix, key);
+            if self.matches(&path) {
+                obj.shift_remove(&key);
+                continue;
+            }
+            let now_empty = match obj.get_mut(&key) {
+                Some(Value::Object(inner)) if !inner.is_empty() => {
+                    self.hide_under(inner, &format!("{}.", path));
+                    inner.is_empty()
+                }
+                _ => false,
+            };
+            if now_empty {
+                obj.shift_remove(&key);
+            }
+        }
+    }
+
+    /// Removes the fields not in the set. Objects are kept with just the
+    /// fields in the set, if they have any.
+    pub fn only(&self, obj: &mut Map<String, Value>) {
+        self.only_under(obj, "");
+    }
+
+    fn only_under(&self, obj: &mut Map<String, Value>, prefix: &str) {
+        let keys: Vec<String> = obj.keys().cloned().collect();
+        for key in keys {
+            let path = format!("{}{}", prefix, key);
+            if self.matches(&path) {
+                continue;
+            }
+            let keep = match obj.get_mut(&key) {
+                Some(Value::Object(inner)) => {
+                    self.only_under(inner, &format!("{}.", path));
+                    !inner.is_empty()
+                }
+                _ => false,
+            };
+            if !keep {
+                obj.shift_remove(&key);
+            }
+        }
+    }
+}
+
+/// The order in which fields are listed after the headline.
+#[derive(Clone, Debug, PartialEq)]
+pub enum KeyOrder {
+    /// Alphabetical
+    Sorted,
+    /// As they appear in the input
+    Original,
+    /// The given keys first, in the given order, then the rest sorted
+    Priority(Vec<String>),
+}
+
+impl KeyOrder {
+    pub fn apply<'a>(&self, obj: &'a Map<String, Value>) -> Vec<&'a String> {
+        let mut keys: Vec<&String> = obj.keys().collect();
+        match *self {
+            KeyOrder::Sorted => keys.sort(),
+            KeyOrder::Original => {}
+            KeyOrder::Priority(ref first) => {
+                let rank = |k: &String| first.iter().position(|f| f == k).unwrap_or(first.len());
+                keys.sort_by(|a, b| rank(a).cmp(&rank(b)).then(a.cmp(b)));
+            }
+        }
+        keys
+    }
+
+    /// Whether `key` is one of the keys to list first.
+    pub fn is_priority(&self, key: &str) -> bool {
+        match *self {
+            KeyOrder::Priority(ref first) => first.iter().any(|f| f == key),
+            _ => false,
+        }
+    }
+
+    /// Puts the keys of any objects within `val` in this order, for values
+    /// that are written out whole as JSON.
+    pub fn order_nested(&self, val: Value) -> Value {
+        if *self == KeyOrder::Original {
+            return val;
+        }
+        match val {
+            Value::Object(mut obj) => {
+                let keys: Vec<String> = self.apply(&obj).into_iter().cloned().collect();
+                Value::Object(
+                    keys.into_iter()
+                        .map(|k| {
+                            let v = obj.remove(&k).unwrap_or(Value::Null);
+                            (k, self.order_nested(v))
+                        })
+                        .collect(),
+                )
+            }
+            Value::Array(arr) => {
+                Value::Array(arr.into_iter().map(|v| self.order_nested(v)).collect())
+            }
+            val => val,
+        }
+    }
+}
+
+impl FromStr for KeyOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<KeyOrder, String> {
+        match s {
+            "sorted" => Ok(KeyOrder::Sorted),
+            "original" => Ok(KeyOrder::Original),
+            _ => match s.strip_prefix("priority:") {
+                Some(keys) => Ok(KeyOrder::Priority(
+                    keys.split(',')
+                        .filter(|k| !k.is_empty())
+                        .map(|k| k.to_string())
+                        .collect(),
+                )),
+                None => Err(format!(
+                    "unknown key order `{}`, expected sorted, original or priority:<k1,k2,...>",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{flatten, lookup, remove, unpack_json, FieldSet, KeyOrder};
+    use serde_json::Value;
+
+    fn obj(json: &str) -> ::serde_json::Map<String, Value> {
+        match ::serde_json::from_str(json).unwrap() {
+            Value::Object(obj) => obj,
+            _ => panic!("not an object"),
+        }
+    }
+
+    #[test]
+    fn lookup_flat_and_nested() {
+        let o =
+            obj("{\"a\": 1, \"log.level\": \"info\", \"http\": {\"req\": {\"method\": \"GET\"}}}");
+        assert_eq!(lookup(&o, "a"), Some(&json!(1)));
+        assert_eq!(lookup(&o, "log.level"), Some(&json!("info")));
+        assert_eq!(lookup(&o, "http.req.method"), Some(&json!("GET")));
+        assert_eq!(lookup(&o, "http.req.path"), None);
+        assert_eq!(lookup(&o, "b"), None);
+    }
+
+    #[test]
+    fn lookup_nested_key_with_dots() {
+        let o = obj("{\"k8s.pod\": {\"name\": \"web\"}}");
+        assert_eq!(lookup(&o, "k8s.pod.name"), Some(&json!("web")));
+    }
+
+    #[test]
+    fn remove_nested() {
+        let mut o = obj("{\"log\": {\"level\": \"info\", \"logger\": \"x\"}, \"a\": 1}");
+        assert_eq!(remove(&mut o, "log.level"), Some(json!("info")));
+        assert_eq!(Value::Object(o), json!({"log": {"logger": "x"}, "a": 1}));
+    }
+
+    #[test]
+    fn remove_prunes_empty_parents() {
+        let mut o = obj("{\"log\": {\"level\": \"info\"}, \"a\": 1}");
+        assert_eq!(remove(&mut o, "log.level"), Some(json!("info")));
+        assert_eq!(Value::Object(o), json!({"a": 1}));
+    }
+
+    #[test]
+    fn remove_missing() {
+        let mut o = obj("{\"a\": 1}");
+        assert_eq!(remove(&mut o, "a.b"), None);
+        assert_eq!(Value::Object(o), json!({"a": 1}));
+    }
+
+    #[test]
+    fn remove_keeps_order() {
+        let mut o = obj("{\"z\": 1, \"a\": 2, \"m\": 3}");
+        remove(&mut o, "z");
+        assert_eq!(o.keys().collect::<Vec<_>>(), vec!["a", "m"]);
+    }
+
+    #[test]
+    fn key_order_parse() {
+        assert_eq!("sorted".parse(), Ok(KeyOrder::Sorted));
+        assert_eq!("original".parse(), Ok(KeyOrder::Original));
+        assert_eq!(
+            "priority:request_id,user".parse(),
+            Ok(KeyOrder::Priority(vec![
+                "request_id".to_string(),
+                "user".to_string()
+            ]))
+        );
+        assert!("random".parse::<KeyOrder>().is_err());
+    }
+
+    #[test]
+    fn key_order_apply() {
+        let o = obj("{\"zone\": 1, \"user\": 2, \"a\": 3, \"request_id\": 4}");
+        assert_eq!(
+            KeyOrder::Sorted.apply(&o),
+            vec!["a", "request_id", "user", "zone"]
+        );
+        assert_eq!(
+            KeyOrder::Original.apply(&o),
+            vec!["zone", "user", "a", "request_id"]
+        );
+        let priority = KeyOrder::Priority(vec!["request_id".to_string(), "user".to_string()]);
+        assert_eq!(priority.apply(&o), vec!["request_id", "user", "a", "zone"]);
+    }
+
+    #[test]
+    fn key_order_nested() {
+        let val = json!({"z": [{"y": 1, "b": 2}], "a": {"d": 3, "c": 4}});
+        assert_eq!(
+            KeyOrder::Sorted.order_nested(val.clone()).to_string(),
+            "{\"a\":{\"c\":4,\"d\":3},\"z\":[{\"b\":2,\"y\":1}]}"
+        );
+        assert_eq!(
+            KeyOrder::Original.order_nested(val).to_string(),
+            "{\"z\":[{\"y\":1,\"b\":2}],\"a\":{\"d\":3,\"c\":4}}"
+        );
+    }
+
+    fn field_set(paths: &[&str]) -> FieldSet {
+        FieldSet::new(&paths.iter().map(|p| p.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn field_set_hide() {
+        let mut o = obj("{\"pid\": 1, \"hostname\": \"h\", \"k8s\": {\"pod\": \"web\", \"ns\": \"prod\"}, \"k8s.node\": \"n1\", \"http\": {\"status\": 200, \"method\": \"GET\"}, \"a\": 1}");
+        field_set(&["pid", "hostname", "k8s.*", "http.method"]).hide(&mut o);
+        assert_eq!(Value::Object(o), json!({"http": {"status": 200}, "a": 1}));
+    }
+
+    #[test]
+    fn field_set_only() {
+        let mut o = obj("{\"pid\": 1, \"user\": \"bob\", \"http\": {\"status\": 200, \"method\": \"GET\"}, \"k8s\": {\"pod\": \"web\"}}");
+        field_set(&["user", "http.status"]).only(&mut o);
+        assert_eq!(
+            Value::Object(o),
+            json!({"user": "bob", "http": {"status": 200}})
+        );
+    }
+
+    #[test]
+    fn field_set_only_glob() {
+        let mut o = obj("{\"req_id\": 1, \"req_path\": \"/\", \"other\": 2}");
+        field_set(&["req_*"]).only(&mut o);
+        assert_eq!(Value::Object(o), json!({"req_id": 1, "req_path": "/"}));
+    }
+
+    #[test]
+    fn field_set_invalid() {
+        assert!(FieldSet::new(&["[".to_string()]).is_err());
+    }
+
+    #[test]
+    fn flatten_nested() {
+        let o = obj("{\"http\": {\"request\": {\"method\": \"GET\"}, \"response\": {\"status\": 200}}, \"tags\": [{\"a\": 1}], \"empty\": {}, \"a\": 1}");
+        let flat = flatten(o);
+        assert_eq!(
+            flat.keys().collect::<Vec<_>>(),
+            vec![
+                "http.request.method",
+                "http.response.status",
+                "tags",
+                "empty",
+                "a"
+            ]
+        );
+        assert_eq!(flat["tags"], json!([{"a": 1}]));
+        assert_eq!(flat["empty"], json!({}));
+    }
+
+    #[test]
+    fn flatten_collisions() {
+        let flat = flatten(obj("{\"a.b\": 1, \"a\": {\"b\": 2}, \"a.b#2\": 3}"));
+        assert_eq!(
+            Value::Object(flat),
+            json!({"a.b": 1, "a.b#2": 2, "a.b#2#2": 3})
+        );
+    }
+
+    #[test]
+    fn unpack_json_strings() {
+        let val = json!({
+            "body": "{\"user\": 5, \"inner\": \"{\\\"a\\\": 1}\"}",
+            "list": ["[1, 2]", "[not json"],
+            "msg": "{braces} in text",
+            "n": "5"
+        });
+        assert_eq!(
+            unpack_json(val.clone(), 1),
+            json!({
+                "body": {"user": 5, "inner": "{\"a\": 1}"},
+                "list": [[1, 2], "[not json"],
+                "msg": "{braces} in text",
+                "n": "5"
+            })
+        );
+        assert_eq!(
+            unpack_json(val.clone(), 2)["body"],
+            json!({"user": 5, "inner": {"a": 1}})
+        );
+        assert_eq!(unpack_json(val.clone(), 0), val);
+    }
+}
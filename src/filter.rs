@@ -0,0 +1,659 @@
+//! A small expression language for selecting records, e.g.
+//! `status >= 500 && path =~ "^/api" && user.id == 42`.
+//!
+//! Expressions are made of comparisons between a field path and a literal
+//! (`==`, `!=`, `<`, `<=`, `>`, `>=`), regex matches (`=~`, `!~`),
+//! `exists(path)`, and the combinators `&&`, `||`, `!` and parentheses.
+//! Paths address nested fields with dots and array elements with `[n]`.
+
+use regex::Regex;
+use serde_json::Value;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    /// Character offset into the expression where the error was found.
+    pub position: usize,
+}
+
+impl ParseError {
+    fn new(message: String, position: usize) -> ParseError {
+        ParseError { message, position }
+    }
+
+    /// Renders the error along with the expression and a caret pointing at
+    /// the offending position.
+    pub fn render(&self, expr: &str) -> String {
+        format!("{}\n  {}\n  {}^", self, expr, " ".repeat(self.position))
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "column {}: {}", self.position + 1, self.message)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(f64),
+    Dot,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    And,
+    Or,
+    Not,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Match,
+    NotMatch,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::Ident(ref s) => write!(f, "`{}`", s),
+            Token::Str(ref s) => write!(f, "\"{}\"", s),
+            Token::Num(n) => write!(f, "`{}`", n),
+            Token::Dot => write!(f, "`.`"),
+            Token::LParen => write!(f, "`(`"),
+            Token::RParen => write!(f, "`)`"),
+            Token::LBracket => write!(f, "`[`"),
+            Token::RBracket => write!(f, "`]`"),
+            Token::And => write!(f, "`&&`"),
+            Token::Or => write!(f, "`||`"),
+            Token::Not => write!(f, "`!`"),
+            Token::Eq => write!(f, "`==`"),
+            Token::Ne => write!(f, "`!=`"),
+            Token::Lt => write!(f, "`<`"),
+            Token::Le => write!(f, "`<=`"),
+            Token::Gt => write!(f, "`>`"),
+            Token::Ge => write!(f, "`>=`"),
+            Token::Match => write!(f, "`=~`"),
+            Token::NotMatch => write!(f, "`!~`"),
+        }
+    }
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == '@' || c == '$'
+}
+
+fn is_ident_char(c: char) -> bool {
+    is_ident_start(c) || c.is_ascii_digit() || c == '-'
+}
+
+fn tokenize(input: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        let next = chars.get(i + 1).cloned();
+        let token = match c {
+            _ if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '.' => Token::Dot,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '[' => Token::LBracket,
+            ']' => Token::RBracket,
+            '&' if next == Some('&') => Token::And,
+            '|' if next == Some('|') => Token::Or,
+            '=' if next == Some('=') => Token::Eq,
+            '=' if next == Some('~') => Token::Match,
+            '!' if next == Some('=') => Token::Ne,
+            '!' if next == Some('~') => Token::NotMatch,
+            '!' => Token::Not,
+            '<' if next == Some('=') => Token::Le,
+            '<' => Token::Lt,
+            '>' if next == Some('=') => Token::Ge,
+            '>' => Token::Gt,
+            '"' | '\'' => {
+                let mut s = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => {
+                            return Err(ParseError::new("unterminated string".to_string(), start))
+                        }
+                        Some(&q) if q == c => break,
+                        Some(&'\\') => {
+                            i += 1;
+                            match chars.get(i) {
+                                Some(&'n') => s.push('\n'),
+                                Some(&'t') => s.push('\t'),
+                                Some(&e) => s.push(e),
+                                None => {
+                                    return Err(ParseError::new(
+                                        "unterminated string".to_string(),
+                                        start,
+                                    ))
+                                }
+                            }
+                        }
+                        Some(&ch) => s.push(ch),
+                    }
+                    i += 1;
+                }
+                tokens.push((Token::Str(s), start));
+                i += 1;
+                continue;
+            }
+            _ if c.is_ascii_digit() || (c == '-' && next.is_some_and(|n| n.is_ascii_digit())) => {
+                i += 1;
+                while i < chars.len()
+                    && (chars[i].is_ascii_digit()
+                        || chars[i] == '.'
+                        || chars[i] == 'e'
+                        || chars[i] == 'E')
+                {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                match text.parse::<f64>() {
+                    Ok(n) => tokens.push((Token::Num(n), start)),
+                    Err(_) => {
+                        return Err(ParseError::new(format!("invalid number `{}`", text), start))
+                    }
+                }
+                continue;
+            }
+            _ if is_ident_start(c) => {
+                while i < chars.len() && is_ident_char(chars[i]) {
+                    i += 1;
+                }
+                tokens.push((Token::Ident(chars[start..i].iter().collect()), start));
+                continue;
+            }
+            _ => {
+                return Err(ParseError::new(
+                    format!("unexpected character `{}`", c),
+                    start,
+                ))
+            }
+        };
+        i += match token {
+            Token::And
+            | Token::Or
+            | Token::Eq
+            | Token::Ne
+            | Token::Le
+            | Token::Ge
+            | Token::Match
+            | Token::NotMatch => 2,
+            _ => 1,
+        };
+        tokens.push((token, start));
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Path {
+    segments: Vec<Segment>,
+}
+
+impl Path {
+    fn lookup<'a>(&self, val: &'a Value) -> Option<&'a Value> {
+        let mut current = val;
+        for segment in &self.segments {
+            current = match *segment {
+                Segment::Key(ref key) => current.get(key.as_str())?,
+                Segment::Index(i) => current.get(i)?,
+            };
+        }
+        Some(current)
+    }
+
+    /// Resolves the path against nested objects, falling back to a flat key
+    /// containing literal dots (e.g. `"log.level": "info"`).
+    fn resolve<'a>(&self, val: &'a Value) -> Option<&'a Value> {
+        if let Some(v) = self.lookup(val) {
+            return Some(v);
+        }
+        let mut dotted = vec![];
+        for segment in &self.segments {
+            match *segment {
+                Segment::Key(ref key) => dotted.push(key.as_str()),
+                Segment::Index(_) => return None,
+            }
+        }
+        if dotted.len() < 2 {
+            return None;
+        }
+        val.get(dotted.join(".").as_str())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Exists(Path),
+    Compare(Path, CmpOp, Value),
+    Match(Path, Regex),
+}
+
+fn as_number(val: &Value) -> Option<f64> {
+    match *val {
+        Value::Number(ref n) => n.as_f64(),
+        Value::String(ref s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn values_equal(field: &Value, literal: &Value) -> bool {
+    match (field, literal) {
+        (&Value::Number(_), _) | (_, &Value::Number(_)) => {
+            match (as_number(field), as_number(literal)) {
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            }
+        }
+        _ => field == literal,
+    }
+}
+
+fn compare(field: &Value, op: CmpOp, literal: &Value) -> bool {
+    match op {
+        CmpOp::Eq => values_equal(field, literal),
+        CmpOp::Ne => !values_equal(field, literal),
+        _ => {
+            let ordering = match (field, literal) {
+                (Value::String(a), Value::String(b)) => a.partial_cmp(b),
+                _ => match (as_number(field), as_number(literal)) {
+                    (Some(a), Some(b)) => a.partial_cmp(&b),
+                    _ => None,
+                },
+            };
+            match ordering {
+                Some(ordering) => match op {
+                    CmpOp::Lt => ordering.is_lt(),
+                    CmpOp::Le => ordering.is_le(),
+                    CmpOp::Gt => ordering.is_gt(),
+                    _ => ordering.is_ge(),
+                },
+                None => false,
+            }
+        }
+    }
+}
+
+impl Expr {
+    fn eval(&self, val: &Value) -> bool {
+        match *self {
+            Expr::And(ref a, ref b) => a.eval(val) && b.eval(val),
+            Expr::Or(ref a, ref b) => a.eval(val) || b.eval(val),
+            Expr::Not(ref e) => !e.eval(val),
+            Expr::Exists(ref path) => path.resolve(val).is_some(),
+            Expr::Compare(ref path, op, ref literal) => match path.resolve(val) {
+                Some(field) => compare(field, op, literal),
+                None => op == CmpOp::Ne,
+            },
+            Expr::Match(ref path, ref re) => match path.resolve(val) {
+                Some(Value::String(s)) => re.is_match(s),
+                Some(Value::Number(n)) => re.is_match(&n.to_string()),
+                Some(Value::Bool(b)) => re.is_match(&b.to_string()),
+                _ => false,
+            },
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|t| &t.0)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.pos).map(|t| t.1).unwrap_or(self.end)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|t| t.0.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn error<T>(&self, expected: &str) -> Result<T, ParseError> {
+        let found = match self.peek() {
+            Some(token) => format!("found {}", token),
+            None => "reached end of filter".to_string(),
+        };
+        Err(ParseError::new(
+            format!("expected {}, {}", expected, found),
+            self.position(),
+        ))
+    }
+
+    fn expect(&mut self, token: Token, expected: &str) -> Result<(), ParseError> {
+        if self.peek() == Some(&token) {
+            self.pos += 1;
+            return Ok(());
+        }
+        self.error(expected)
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
+        match self.peek() {
+            Some(&Token::Not) => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.parse_unary()?)))
+            }
+            Some(&Token::LParen) => {
+                self.pos += 1;
+                let expr = self.parse_or()?;
+                self.expect(Token::RParen, "`)`")?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name))
+                if name == "exists"
+                    && self.tokens.get(self.pos + 1).map(|t| &t.0) == Some(&Token::LParen) =>
+            {
+                self.pos += 2;
+                let path = self.parse_path()?;
+                self.expect(Token::RParen, "`)`")?;
+                Ok(Expr::Exists(path))
+            }
+            _ => self.parse_comparison(),
+        }
+    }
+
+    fn parse_path(&mut self) -> Result<Path, ParseError> {
+        let mut segments = vec![];
+        match self.peek() {
+            Some(Token::Ident(name)) => segments.push(Segment::Key(name.clone())),
+            _ => return self.error("a field name"),
+        }
+        self.pos += 1;
+        loop {
+            match self.peek() {
+                Some(&Token::Dot) => {
+                    self.pos += 1;
+                    match self.next() {
+                        Some(Token::Ident(name)) => segments.push(Segment::Key(name)),
+                        Some(Token::Str(name)) => segments.push(Segment::Key(name)),
+                        _ => {
+                            self.pos -= 1;
+                            return self.error("a field name after `.`");
+                        }
+                    }
+                }
+                Some(&Token::LBracket) => {
+                    self.pos += 1;
+                    match self.next() {
+                        Some(Token::Num(n)) if n >= 0.0 && n.fract() == 0.0 => {
+                            segments.push(Segment::Index(n as usize))
+                        }
+                        Some(Token::Str(name)) => segments.push(Segment::Key(name)),
+                        _ => {
+                            self.pos -= 1;
+                            return self.error("an array index or quoted key");
+                        }
+                    }
+                    self.expect(Token::RBracket, "`]`")?;
+                }
+                _ => return Ok(Path { segments }),
+            }
+        }
+    }
+
+    fn parse_literal(&mut self) -> Result<Value, ParseError> {
+        let literal = match self.peek() {
+            Some(Token::Str(s)) => Value::String(s.clone()),
+            Some(&Token::Num(n)) => json!(n),
+            Some(Token::Ident(name)) if name == "true" => Value::Bool(true),
+            Some(Token::Ident(name)) if name == "false" => Value::Bool(false),
+            Some(Token::Ident(name)) if name == "null" => Value::Null,
+            _ => return self.error("a value (number, string, true, false or null)"),
+        };
+        self.pos += 1;
+        Ok(literal)
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, ParseError> {
+        let path = self.parse_path()?;
+        let op = match self.peek() {
+            Some(&Token::Eq) => CmpOp::Eq,
+            Some(&Token::Ne) => CmpOp::Ne,
+            Some(&Token::Lt) => CmpOp::Lt,
+            Some(&Token::Le) => CmpOp::Le,
+            Some(&Token::Gt) => CmpOp::Gt,
+            Some(&Token::Ge) => CmpOp::Ge,
+            Some(&Token::Match) | Some(&Token::NotMatch) => {
+                let negate = self.peek() == Some(&Token::NotMatch);
+                self.pos += 1;
+                let position = self.position();
+                let pattern = match self.next() {
+                    Some(Token::Str(pattern)) => pattern,
+                    _ => {
+                        self.pos -= 1;
+                        return self.error("a quoted regular expression");
+                    }
+                };
+                let re = match Regex::new(&pattern) {
+                    Ok(re) => re,
+                    Err(err) => {
+                        return Err(ParseError::new(
+                            format!("invalid regular expression: {}", err),
+                            position,
+                        ))
+                    }
+                };
+                let expr = Expr::Match(path, re);
+                return Ok(if negate {
+                    Expr::Not(Box::new(expr))
+                } else {
+                    expr
+                });
+            }
+            _ => return self.error("a comparison operator"),
+        };
+        self.pos += 1;
+        let literal = self.parse_literal()?;
+        Ok(Expr::Compare(path, op, literal))
+    }
+}
+
+/// A compiled filter expression.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    expr: Expr,
+}
+
+impl Filter {
+    pub fn parse(input: &str) -> Result<Filter, ParseError> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            end: input.chars().count(),
+        };
+        let expr = parser.parse_or()?;
+        if parser.peek().is_some() {
+            return parser.error("`&&`, `||` or end of filter");
+        }
+        Ok(Filter { expr })
+    }
+
+    /// Whether the record matches the filter.
+    pub fn matches(&self, val: &Value) -> bool {
+        self.expr.eval(val)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Filter;
+    use serde_json::Value;
+
+    fn record() -> Value {
+        json!({
+            "status": 503,
+            "path": "/api/users",
+            "user": {"id": 42, "name": "ada"},
+            "tags": ["a", "b"],
+            "code": "404",
+            "ok": false,
+            "log.level": "info",
+        })
+    }
+
+    fn matches(expr: &str) -> bool {
+        Filter::parse(expr).unwrap().matches(&record())
+    }
+
+    #[test]
+    fn filter_comparisons() {
+        assert!(matches("status >= 500"));
+        assert!(matches("status == 503"));
+        assert!(matches("status != 500"));
+        assert!(!matches("status < 500"));
+        assert!(matches("user.name == \"ada\""));
+        assert!(matches("user.name > 'a'"));
+        assert!(matches("ok == false"));
+    }
+
+    #[test]
+    fn filter_numeric_strings() {
+        assert!(matches("code == 404"));
+        assert!(matches("code >= 400"));
+    }
+
+    #[test]
+    fn filter_nested_paths() {
+        assert!(matches("user.id == 42"));
+        assert!(matches("tags[1] == \"b\""));
+        assert!(matches("log.level == \"info\""));
+    }
+
+    #[test]
+    fn filter_regex() {
+        assert!(matches("path =~ \"^/api\""));
+        assert!(!matches("path =~ \"^/admin\""));
+        assert!(matches("path !~ \"^/admin\""));
+        assert!(matches("status =~ \"^5\""));
+    }
+
+    #[test]
+    fn filter_exists() {
+        assert!(matches("exists(user.id)"));
+        assert!(!matches("exists(user.email)"));
+        assert!(matches("!exists(user.email)"));
+    }
+
+    #[test]
+    fn filter_missing_fields() {
+        assert!(!matches("missing == 1"));
+        assert!(!matches("missing > 1"));
+        assert!(matches("missing != 1"));
+        assert!(!matches("missing =~ \".*\""));
+    }
+
+    #[test]
+    fn filter_combinators() {
+        assert!(matches(
+            "status >= 500 && path =~ \"^/api\" && user.id == 42"
+        ));
+        assert!(matches("status < 500 || user.id == 42"));
+        assert!(!matches("status < 500 || (user.id == 42 && ok == true)"));
+        assert!(matches("!(status < 500)"));
+    }
+
+    #[test]
+    fn filter_precedence() {
+        // && binds tighter than ||
+        assert!(matches("user.id == 42 || status < 500 && ok == true"));
+    }
+
+    #[test]
+    fn filter_parse_errors() {
+        let err = Filter::parse("status >= && x").unwrap_err();
+        assert_eq!(err.position, 10);
+        assert_eq!(
+            err.message,
+            "expected a value (number, string, true, false or null), found `&&`"
+        );
+
+        let err = Filter::parse("status").unwrap_err();
+        assert_eq!(
+            err.message,
+            "expected a comparison operator, reached end of filter"
+        );
+
+        let err = Filter::parse("(status == 1").unwrap_err();
+        assert_eq!(err.message, "expected `)`, reached end of filter");
+
+        let err = Filter::parse("path =~ \"(\"").unwrap_err();
+        assert!(err.message.starts_with("invalid regular expression"));
+
+        let err = Filter::parse("path == \"abc").unwrap_err();
+        assert_eq!(err.message, "unterminated string");
+
+        let err = Filter::parse("status == 1 status").unwrap_err();
+        assert_eq!(err.position, 12);
+    }
+
+    #[test]
+    fn filter_render_error() {
+        let err = Filter::parse("a == ").unwrap_err();
+        assert_eq!(
+            err.render("a == "),
+            "column 6: expected a value (number, string, true, false or null), reached end of filter\n  a == \n       ^"
+        );
+    }
+}
@@ -0,0 +1,469 @@
+use glob;
+use input::Compression;
+use std::fs::{self, File, Metadata};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// How long to wait between checks for new data once every file is drained.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Identifies the file behind a path so that replacement by rotation can be
+/// told apart from appends.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct FileId {
+    dev: u64,
+    ino: u64,
+}
+
+#[cfg(unix)]
+fn file_id(meta: &Metadata) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    Some(FileId {
+        dev: meta.dev(),
+        ino: meta.ino(),
+    })
+}
+
+#[cfg(not(unix))]
+fn file_id(_meta: &Metadata) -> Option<FileId> {
+    None
+}
+
+/// How much of the start of a file is remembered, to notice it being
+/// truncated and rewritten past the old read position between polls.
+const HEAD_LEN: usize = 64;
+
+/// Something that happened to a followed file.
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// A line was read, including its trailing newline if it has one.
+    Line(&'a str),
+    /// The file was truncated or replaced, and is read again from the start.
+    Reset,
+    /// The file couldn't be read. It's retried on the next poll, and the
+    /// error isn't reported again until a read succeeds.
+    Error(io::Error),
+}
+
+struct FollowedFile {
+    path: PathBuf,
+    reader: Option<BufReader<File>>,
+    id: Option<FileId>,
+    pos: u64,
+    partial: Vec<u8>,
+    head: Vec<u8>,
+    failed: bool,
+}
+
+impl FollowedFile {
+    fn new(path: PathBuf) -> FollowedFile {
+        FollowedFile {
+            path,
+            reader: None,
+            id: None,
+            pos: 0,
+            partial: vec![],
+            head: vec![],
+            failed: false,
+        }
+    }
+
+    /// Opens the file afresh. A missing file isn't an error, as it may be
+    /// created later or be mid-rotation.
+    fn reopen(&mut self) -> io::Result<()> {
+        self.reader = None;
+        self.id = None;
+        self.pos = 0;
+        self.partial.clear();
+        self.head.clear();
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        self.id = file.metadata().ok().and_then(|meta| file_id(&meta));
+        self.reader = Some(BufReader::new(file));
+        Ok(())
+    }
+
+    /// Reads every complete line currently available. A trailing line without
+    /// a newline is held back until the rest of it is written.
+    fn drain<F: FnMut(&Path, Event)>(&mut self, on_event: &mut F) -> io::Result<bool> {
+        let mut read_any = false;
+        let reader = match self.reader {
+            Some(ref mut reader) => reader,
+            None => return Ok(false),
+        };
+        loop {
+            let start = self.partial.len();
+            let n = reader.read_until(b'\n', &mut self.partial)?;
+            if n == 0 {
+                return Ok(read_any);
+            }
+            if self.head.len() < HEAD_LEN && self.head.len() as u64 == self.pos {
+                let take = n.min(HEAD_LEN - self.head.len());
+                self.head
+                    .extend_from_slice(&self.partial[start..start + take]);
+            }
+            self.pos += n as u64;
+            if self.partial.last() == Some(&b'\n') {
+                read_any = true;
+                on_event(
+                    &self.path,
+                    Event::Line(&String::from_utf8_lossy(&self.partial)),
+                );
+                self.partial.clear();
+            }
+        }
+    }
+
+    /// Flushes a held back partial line, used when the file is going away.
+    fn flush_partial<F: FnMut(&Path, Event)>(&mut self, on_event: &mut F) {
+        if !self.partial.is_empty() {
+            on_event(
+                &self.path,
+                Event::Line(&String::from_utf8_lossy(&self.partial)),
+            );
+            self.partial.clear();
+        }
+    }
+
+    /// Whether the file still starts with what was read from it. Catches a
+    /// file truncated and refilled past the read position between polls,
+    /// unless the new contents happen to start the same way.
+    fn same_head(&self) -> bool {
+        if self.head.is_empty() {
+            return true;
+        }
+        let mut head = vec![0; self.head.len()];
+        match File::open(&self.path).and_then(|mut file| file.read_exact(&mut head)) {
+            Ok(()) => head == self.head,
+            Err(_) => false,
+        }
+    }
+
+    fn poll<F: FnMut(&Path, Event)>(&mut self, on_event: &mut F) -> io::Result<bool> {
+        if self.reader.is_none() {
+            self.reopen()?;
+        }
+        let mut read_any = false;
+
+        // The path may have been removed mid-rotation; keep reading the old
+        // file until something new shows up in its place
+        if let Ok(meta) = fs::metadata(&self.path) {
+            if self.reader.is_none() || (self.id.is_some() && file_id(&meta) != self.id) {
+                // Replaced by rotation, so finish the old file and start over
+                // on the new one
+                let replaced = self.reader.is_some();
+                read_any |= self.drain(on_event)?;
+                self.flush_partial(on_event);
+                if replaced {
+                    on_event(&self.path, Event::Reset);
+                }
+                self.reopen()?;
+            } else if meta.len() < self.pos || (meta.len() > self.pos && !self.same_head()) {
+                // Truncated in place, and maybe written to again since
+                self.partial.clear();
+                self.head.clear();
+                self.pos = 0;
+                if let Some(ref mut reader) = self.reader {
+                    reader.seek(SeekFrom::Start(0))?;
+                }
+                on_event(&self.path, Event::Reset);
+            }
+        }
+        read_any |= self.drain(on_event)?;
+        Ok(read_any)
+    }
+}
+
+/// Follows a set of files like `tail -F`: reads them from the start, then
+/// keeps reading lines as they are appended, reopening files that are
+/// truncated or replaced by log rotation.
+pub struct Follower {
+    files: Vec<FollowedFile>,
+    patterns: Vec<String>,
+}
+
+impl Follower {
+    pub fn new(paths: Vec<PathBuf>) -> Follower {
+        Follower {
+            files: paths.into_iter().map(FollowedFile::new).collect(),
+            patterns: vec![],
+        }
+    }
+
+    /// Also follows files that come to match these glob patterns later on,
+    /// reading them from the start. Compressed files are skipped, as are
+    /// followed files that were renamed to match.
+    pub fn watch(&mut self, patterns: Vec<String>) {
+        self.patterns = patterns;
+    }
+
+    fn discover(&mut self) {
+        for pattern in &self.patterns {
+            let entries = match glob::glob(pattern) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for path in entries.filter_map(|entry| entry.ok()) {
+                if self.files.iter().any(|file| file.path == path) {
+                    continue;
+                }
+                let meta = match fs::metadata(&path) {
+                    Ok(meta) => meta,
+                    Err(_) => continue,
+                };
+                if !meta.is_file() {
+                    continue;
+                }
+                let id = file_id(&meta);
+                if id.is_some() && self.files.iter().any(|file| file.id == id) {
+                    continue;
+                }
+                if Compression::of_file(&path).ok() != Some(Compression::None) {
+                    continue;
+                }
+                self.files.push(FollowedFile::new(path));
+            }
+        }
+    }
+
+    /// Reads whatever is currently available from every file, calling
+    /// `on_event` for each complete line and for read errors. Returns
+    /// whether anything was read.
+    pub fn poll<F: FnMut(&Path, Event)>(&mut self, mut on_event: F) -> bool {
+        self.discover();
+        let mut read_any = false;
+        for file in &mut self.files {
+            match file.poll(&mut on_event) {
+                Ok(read) => {
+                    read_any |= read;
+                    file.failed = false;
+                }
+                Err(err) => {
+                    if !file.failed {
+                        on_event(&file.path, Event::Error(err));
+                    }
+                    file.failed = true;
+                }
+            }
+        }
+        read_any
+    }
+
+    /// Polls the files forever, sleeping whenever there is nothing new.
+    pub fn run<F: FnMut(&Path, Event)>(&mut self, mut on_event: F) -> ! {
+        loop {
+            if !self.poll(&mut on_event) {
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, Follower};
+    use std::env;
+    use std::fs::{self, File, OpenOptions};
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    fn temp_file(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("structy-follow-{}-{}", name, ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("app.log")
+    }
+
+    fn append(path: &PathBuf, data: &str) {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(data.as_bytes()).unwrap();
+    }
+
+    fn poll_lines(follower: &mut Follower) -> Vec<String> {
+        let mut lines = vec![];
+        follower.poll(|_, event| match event {
+            Event::Line(line) => lines.push(line.to_string()),
+            Event::Reset => {}
+            Event::Error(err) => panic!("{}", err),
+        });
+        lines
+    }
+
+    #[test]
+    fn follow_appended_lines() {
+        let path = temp_file("append");
+        append(&path, "one\ntwo\n");
+        let mut follower = Follower::new(vec![path.clone()]);
+        assert_eq!(poll_lines(&mut follower), vec!["one\n", "two\n"]);
+        assert!(poll_lines(&mut follower).is_empty());
+
+        append(&path, "three\n");
+        assert_eq!(poll_lines(&mut follower), vec!["three\n"]);
+    }
+
+    #[test]
+    fn follow_holds_back_partial_lines() {
+        let path = temp_file("partial");
+        append(&path, "one\ntw");
+        let mut follower = Follower::new(vec![path.clone()]);
+        assert_eq!(poll_lines(&mut follower), vec!["one\n"]);
+
+        append(&path, "o\n");
+        assert_eq!(poll_lines(&mut follower), vec!["two\n"]);
+    }
+
+    #[test]
+    fn follow_truncated_file() {
+        let path = temp_file("truncate");
+        append(&path, "one\ntwo\n");
+        let mut follower = Follower::new(vec![path.clone()]);
+        assert_eq!(poll_lines(&mut follower).len(), 2);
+
+        File::create(&path).unwrap();
+        append(&path, "new\n");
+        assert_eq!(poll_lines(&mut follower), vec!["new\n"]);
+    }
+
+    #[test]
+    fn follow_rotated_file() {
+        let path = temp_file("rotate");
+        append(&path, "one\n");
+        let mut follower = Follower::new(vec![path.clone()]);
+        assert_eq!(poll_lines(&mut follower), vec!["one\n"]);
+
+        let rotated = path.with_extension("log.1");
+        fs::rename(&path, &rotated).unwrap();
+        append(&rotated, "two\n");
+        assert_eq!(poll_lines(&mut follower), vec!["two\n"]);
+
+        append(&path, "fresh file, longer than the old one\n");
+        assert_eq!(
+            poll_lines(&mut follower),
+            vec!["fresh file, longer than the old one\n"]
+        );
+    }
+
+    #[test]
+    fn follow_reports_resets() {
+        let path = temp_file("reset");
+        append(&path, "one\n");
+        let mut follower = Follower::new(vec![path.clone()]);
+        let mut events = vec![];
+        let mut record = |follower: &mut Follower| {
+            follower.poll(|_, event| match event {
+                Event::Line(line) => events.push(line.to_string()),
+                Event::Reset => events.push("reset".to_string()),
+                Event::Error(err) => panic!("{}", err),
+            });
+        };
+        record(&mut follower);
+
+        File::create(&path).unwrap();
+        append(&path, "second\n");
+        record(&mut follower);
+
+        fs::rename(&path, path.with_extension("log.1")).unwrap();
+        append(&path, "three, after rotation\n");
+        record(&mut follower);
+        assert_eq!(
+            events,
+            vec![
+                "one\n",
+                "reset",
+                "second\n",
+                "reset",
+                "three, after rotation\n"
+            ]
+        );
+    }
+
+    #[test]
+    fn follow_file_created_later() {
+        let path = temp_file("later");
+        let mut follower = Follower::new(vec![path.clone()]);
+        assert!(poll_lines(&mut follower).is_empty());
+
+        append(&path, "hello\n");
+        assert_eq!(poll_lines(&mut follower), vec!["hello\n"]);
+    }
+
+    #[test]
+    fn follow_truncated_and_refilled_file() {
+        let path = temp_file("refill");
+        append(&path, "one\n");
+        let mut follower = Follower::new(vec![path.clone()]);
+        assert_eq!(poll_lines(&mut follower), vec!["one\n"]);
+
+        File::create(&path).unwrap();
+        append(&path, "rewritten\nafter\n");
+        assert_eq!(poll_lines(&mut follower), vec!["rewritten\n", "after\n"]);
+    }
+
+    #[test]
+    fn follow_files_matching_pattern_later() {
+        let path = temp_file("watch");
+        append(&path, "one\n");
+        let mut follower = Follower::new(vec![path.clone()]);
+        follower.watch(vec![path.with_file_name("*.log").display().to_string()]);
+        assert_eq!(poll_lines(&mut follower), vec!["one\n"]);
+
+        let other = path.with_file_name("other.log");
+        append(&other, "two\n");
+        assert_eq!(poll_lines(&mut follower), vec!["two\n"]);
+
+        // A followed file renamed to match isn't read again
+        fs::rename(&path, path.with_file_name("moved.log")).unwrap();
+        assert!(poll_lines(&mut follower).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follow_reports_errors_and_keeps_going() {
+        let path = temp_file("error");
+        let dir = path.with_file_name("dir.log");
+        fs::create_dir_all(&dir).unwrap();
+        append(&path, "one\n");
+        let mut follower = Follower::new(vec![dir.clone(), path.clone()]);
+        let mut lines = vec![];
+        let mut errors = vec![];
+        for _ in 0..2 {
+            follower.poll(|p, event| match event {
+                Event::Line(line) => lines.push(line.to_string()),
+                Event::Reset => {}
+                Event::Error(_) => errors.push(p.to_path_buf()),
+            });
+        }
+        assert_eq!(lines, vec!["one\n"]);
+        assert_eq!(errors, vec![dir]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follow_reports_unopenable_files() {
+        let path = temp_file("unopenable");
+        append(&path, "one\n");
+        // A path below a file can never be opened
+        let bad = path.join("app.log");
+        let mut follower = Follower::new(vec![bad.clone(), path.clone()]);
+        let mut lines = vec![];
+        let mut errors = vec![];
+        for _ in 0..2 {
+            follower.poll(|p, event| match event {
+                Event::Line(line) => lines.push(line.to_string()),
+                Event::Reset => {}
+                Event::Error(_) => errors.push(p.to_path_buf()),
+            });
+        }
+        assert_eq!(lines, vec!["one\n"]);
+        assert_eq!(errors, vec![bad]);
+    }
+}
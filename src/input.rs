@@ -0,0 +1,273 @@
+use bzip2::bufread::MultiBzDecoder;
+use flate2::bufread::MultiGzDecoder;
+use glob::glob;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use zstd::stream::read::Decoder as ZstdDecoder;
+
+/// Compression formats recognised by their leading magic bytes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    pub fn from_magic(magic: &[u8]) -> Compression {
+        if magic.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if let [b'B', b'Z', b'h', b'1'..=b'9', ..] = magic {
+            // The fourth byte is the block size, which tells it apart from text
+            Compression::Bzip2
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Sniffs the compression of the file at `path`.
+    pub fn of_file(path: &Path) -> io::Result<Compression> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Ok(Compression::from_magic(reader.fill_buf()?))
+    }
+}
+
+/// Wraps `reader` in a decompressor when its contents start with the magic
+/// bytes of a supported compression format. Anything else is passed through.
+/// Only the data already buffered is looked at, so a live pipe whose first
+/// write is short isn't held up waiting for more.
+pub fn decompress<R: BufRead + 'static>(mut reader: R) -> io::Result<Box<dyn BufRead>> {
+    let compression = Compression::from_magic(reader.fill_buf()?);
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Compression::Zstd => Box::new(BufReader::new(ZstdDecoder::with_buffer(reader)?)),
+        Compression::Bzip2 => Box::new(BufReader::new(MultiBzDecoder::new(reader))),
+    })
+}
+
+/// A place to read log lines from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    Stdin,
+    File(PathBuf),
+}
+
+impl Source {
+    /// Opens the source for reading, decompressing it if needed.
+    pub fn open(&self) -> io::Result<Box<dyn BufRead>> {
+        match *self {
+            Source::Stdin => decompress(BufReader::new(io::stdin())),
+            Source::File(ref path) => decompress(BufReader::new(File::open(path)?)),
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Source::Stdin => write!(f, "stdin"),
+            Source::File(ref path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+fn is_glob_pattern(arg: &str) -> bool {
+    arg.contains('*') || arg.contains('?') || arg.contains('[')
+}
+
+/// The command line arguments that `expand_paths` expands as glob patterns.
+pub fn patterns(args: &[String]) -> Vec<String> {
+    args.iter()
+        .filter(|arg| !Path::new(arg).exists() && is_glob_pattern(arg))
+        .cloned()
+        .collect()
+}
+
+/// Turns command line arguments into input sources. `-` means stdin, and
+/// arguments that don't name an existing file are expanded as glob patterns.
+/// With no arguments at all, stdin is read.
+pub fn expand_paths(args: &[String]) -> Result<Vec<Source>, String> {
+    if args.is_empty() {
+        return Ok(vec![Source::Stdin]);
+    }
+
+    let mut sources = vec![];
+    for arg in args {
+        if arg == "-" {
+            sources.push(Source::Stdin);
+            continue;
+        }
+        if Path::new(arg).exists() || !is_glob_pattern(arg) {
+            sources.push(Source::File(PathBuf::from(arg)));
+            continue;
+        }
+
+        let entries = match glob(arg) {
+            Ok(entries) => entries,
+            Err(err) => return Err(format!("{}: invalid pattern: {}", arg, err)),
+        };
+        let mut matched: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|path| path.is_file())
+            .collect();
+        if matched.is_empty() {
+            return Err(format!("{}: no files match pattern", arg));
+        }
+        matched.sort();
+        sources.extend(matched.into_iter().map(Source::File));
+    }
+    Ok(sources)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decompress, expand_paths, Compression, Source};
+    use bzip2::write::BzEncoder;
+    use flate2::write::GzEncoder;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{BufRead, Write};
+    use std::path::PathBuf;
+
+    const LINES: &str = "{\"a\": 1}\n{\"a\": 2}\n";
+
+    fn read_lines(data: Vec<u8>) -> Vec<String> {
+        let reader = decompress(::std::io::Cursor::new(data)).unwrap();
+        reader.lines().map(|l| l.unwrap()).collect()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("structy-input-{}-{}", name, ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn expand_no_args_reads_stdin() {
+        assert_eq!(expand_paths(&[]).unwrap(), vec![Source::Stdin]);
+    }
+
+    #[test]
+    fn expand_dash_reads_stdin() {
+        let args = vec!["-".to_string()];
+        assert_eq!(expand_paths(&args).unwrap(), vec![Source::Stdin]);
+    }
+
+    #[test]
+    fn expand_plain_paths_in_order() {
+        let args = vec!["b.log".to_string(), "a.log".to_string()];
+        assert_eq!(
+            expand_paths(&args).unwrap(),
+            vec![
+                Source::File(PathBuf::from("b.log")),
+                Source::File(PathBuf::from("a.log")),
+            ]
+        );
+    }
+
+    #[test]
+    fn expand_glob_pattern() {
+        let dir = temp_dir("glob");
+        File::create(dir.join("b.json")).unwrap();
+        File::create(dir.join("a.json")).unwrap();
+        File::create(dir.join("c.txt")).unwrap();
+
+        let pattern = dir.join("*.json").to_string_lossy().to_string();
+        assert_eq!(
+            expand_paths(&[pattern]).unwrap(),
+            vec![
+                Source::File(dir.join("a.json")),
+                Source::File(dir.join("b.json")),
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn expand_glob_pattern_without_matches() {
+        let dir = temp_dir("nomatch");
+        let pattern = dir.join("*.json").to_string_lossy().to_string();
+        assert!(expand_paths(&[pattern]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compression_from_magic() {
+        assert_eq!(
+            Compression::from_magic(&[0x1f, 0x8b, 8, 0]),
+            Compression::Gzip
+        );
+        assert_eq!(
+            Compression::from_magic(&[0x28, 0xb5, 0x2f, 0xfd]),
+            Compression::Zstd
+        );
+        assert_eq!(Compression::from_magic(b"BZh9"), Compression::Bzip2);
+        assert_eq!(Compression::from_magic(b"BZhello"), Compression::None);
+        assert_eq!(Compression::from_magic(b"BZh0"), Compression::None);
+        assert_eq!(Compression::from_magic(b"{\"a\""), Compression::None);
+        assert_eq!(Compression::from_magic(b""), Compression::None);
+    }
+
+    #[test]
+    fn decompress_plain() {
+        assert_eq!(
+            read_lines(LINES.as_bytes().to_vec()),
+            vec!["{\"a\": 1}", "{\"a\": 2}"]
+        );
+    }
+
+    #[test]
+    fn decompress_short_plain() {
+        assert_eq!(read_lines(b"5\n".to_vec()), vec!["5"]);
+    }
+
+    /// Gives one short write, then fails if read again, like a pipe whose
+    /// writer has paused.
+    struct PausedPipe(bool);
+
+    impl ::std::io::Read for PausedPipe {
+        fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> {
+            assert!(!self.0, "read past the first write");
+            self.0 = true;
+            buf[..3].copy_from_slice(b"ok\n");
+            Ok(3)
+        }
+    }
+
+    #[test]
+    fn decompress_doesnt_wait_for_more_input() {
+        let mut reader = decompress(::std::io::BufReader::new(PausedPipe(false))).unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "ok\n");
+    }
+
+    #[test]
+    fn decompress_gzip() {
+        let mut encoder = GzEncoder::new(vec![], ::flate2::Compression::default());
+        encoder.write_all(LINES.as_bytes()).unwrap();
+        let data = encoder.finish().unwrap();
+        assert_eq!(read_lines(data), vec!["{\"a\": 1}", "{\"a\": 2}"]);
+    }
+
+    #[test]
+    fn decompress_zstd() {
+        let data = ::zstd::encode_all(LINES.as_bytes(), 0).unwrap();
+        assert_eq!(read_lines(data), vec!["{\"a\": 1}", "{\"a\": 2}"]);
+    }
+
+    #[test]
+    fn decompress_bzip2() {
+        let mut encoder = BzEncoder::new(vec![], ::bzip2::Compression::default());
+        encoder.write_all(LINES.as_bytes()).unwrap();
+        let data = encoder.finish().unwrap();
+        assert_eq!(read_lines(data), vec!["{\"a\": 1}", "{\"a\": 2}"]);
+    }
+}
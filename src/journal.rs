@@ -0,0 +1,157 @@
+//! Records from `journalctl -o json`. Every value is a string, the time is
+//! `__REALTIME_TIMESTAMP` in microseconds and the level is a syslog
+//! `PRIORITY`.
+
+use level::{Level, LevelScale};
+use serde_json::{Map, Number, Value};
+use timestamp::{self, EpochUnit};
+
+/// Journal bookkeeping that isn't worth showing. Fields starting with
+/// `__` are dropped too.
+const HIDDEN: &[&str] = &[
+    "_BOOT_ID",
+    "_MACHINE_ID",
+    "_TRANSPORT",
+    "_STREAM_ID",
+    "_CAP_EFFECTIVE",
+    "_SELINUX_CONTEXT",
+    "_SOURCE_REALTIME_TIMESTAMP",
+    "_SOURCE_MONOTONIC_TIMESTAMP",
+    "_SYSTEMD_INVOCATION_ID",
+    "SYSLOG_FACILITY",
+];
+
+pub fn is_entry(obj: &Map<String, Value>) -> bool {
+    obj.contains_key("__REALTIME_TIMESTAMP") && obj.contains_key("MESSAGE")
+}
+
+/// Splits a journal entry into its `MESSAGE` and the remaining fields,
+/// with the time, level and unit under `time`, `level` and `unit`.
+pub fn unwrap(mut obj: Map<String, Value>) -> (Value, Map<String, Value>) {
+    let message = obj
+        .shift_remove("MESSAGE")
+        .map_or(Value::Null, decode_bytes);
+    let mut fields = Map::new();
+
+    let time = obj
+        .get("__REALTIME_TIMESTAMP")
+        .and_then(|t| t.as_str())
+        .and_then(|t| t.parse::<u64>().ok())
+        .and_then(|t| timestamp::from_epoch(&Number::from(t), Some(EpochUnit::Micros)));
+    if let Some(time) = time {
+        fields.insert(
+            "time".to_string(),
+            json!(timestamp::render(&time, None, None)),
+        );
+    }
+    let level = obj
+        .shift_remove("PRIORITY")
+        .and_then(|p| p.as_str().and_then(|p| p.parse::<f64>().ok()))
+        .and_then(|p| Level::from_number(p, Some(LevelScale::Syslog)));
+    if let Some(level) = level {
+        fields.insert("level".to_string(), json!(level.name()));
+    }
+    if let Some(unit) = obj.shift_remove("_SYSTEMD_UNIT") {
+        fields.insert("unit".to_string(), unit);
+    }
+
+    for (k, v) in obj {
+        if k.starts_with("__") || HIDDEN.contains(&k.as_str()) {
+            continue;
+        }
+        fields.insert(k, v);
+    }
+    (message, fields)
+}
+
+/// Journal values that aren't valid UTF-8 are written as arrays of bytes.
+/// They're decoded, with invalid sequences replaced.
+fn decode_bytes(val: Value) -> Value {
+    let bytes: Option<Vec<u8>> = match val {
+        Value::Array(ref arr) => arr
+            .iter()
+            .map(|b| b.as_u64().filter(|b| *b <= 0xff).map(|b| b as u8))
+            .collect(),
+        _ => None,
+    };
+    match bytes {
+        Some(bytes) => Value::String(String::from_utf8_lossy(&bytes).into_owned()),
+        None => val,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_entry, unwrap};
+    use serde_json::{Map, Value};
+
+    fn obj(val: Value) -> Map<String, Value> {
+        match val {
+            Value::Object(obj) => obj,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn unwrap_entry() {
+        let obj = obj(json!({
+            "__CURSOR": "s=abc",
+            "__REALTIME_TIMESTAMP": "1706489443176123",
+            "__MONOTONIC_TIMESTAMP": "123",
+            "_BOOT_ID": "b",
+            "PRIORITY": "3",
+            "_SYSTEMD_UNIT": "nginx.service",
+            "_PID": "42",
+            "MESSAGE": "upstream timed out"
+        }));
+        assert!(is_entry(&obj));
+        let (message, fields) = unwrap(obj);
+        assert_eq!(message, json!("upstream timed out"));
+        assert_eq!(
+            Value::Object(fields),
+            json!({
+                "time": "2024-01-29T00:50:43.176Z",
+                "level": "error",
+                "unit": "nginx.service",
+                "_PID": "42"
+            })
+        );
+    }
+
+    #[test]
+    fn unwrap_entry_without_priority() {
+        let (_, fields) = unwrap(obj(json!({
+            "__REALTIME_TIMESTAMP": "1706489443176123",
+            "MESSAGE": "hi"
+        })));
+        assert_eq!(
+            Value::Object(fields),
+            json!({"time": "2024-01-29T00:50:43.176Z"})
+        );
+    }
+
+    #[test]
+    fn unwrap_entry_with_bad_timestamp() {
+        let (message, fields) = unwrap(obj(json!({
+            "__REALTIME_TIMESTAMP": "soon",
+            "PRIORITY": "6",
+            "MESSAGE": "hi"
+        })));
+        assert_eq!(message, json!("hi"));
+        assert_eq!(Value::Object(fields), json!({"level": "info"}));
+    }
+
+    #[test]
+    fn unwrap_entry_with_byte_message() {
+        let (message, _) = unwrap(obj(json!({
+            "__REALTIME_TIMESTAMP": "1706489443176123",
+            "MESSAGE": [104, 105, 0xff, 33]
+        })));
+        assert_eq!(message, json!("hi\u{fffd}!"));
+        let (message, _) = unwrap(obj(json!({
+            "__REALTIME_TIMESTAMP": "1706489443176123",
+            "MESSAGE": [1, "x"]
+        })));
+        assert_eq!(message, json!([1, "x"]));
+    }
+}
@@ -0,0 +1,172 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Log severities, ordered from least to most severe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+impl Level {
+    /// Recognises level names case-insensitively, including common aliases.
+    pub fn from_name(name: &str) -> Option<Level> {
+        match name.to_lowercase().as_str() {
+            "trace" | "verbose" => Some(Level::Trace),
+            "debug" => Some(Level::Debug),
+            "info" | "information" | "notice" => Some(Level::Info),
+            "warn" | "warning" => Some(Level::Warn),
+            "error" | "err" => Some(Level::Error),
+            "fatal" | "critical" | "crit" | "panic" | "alert" | "emerg" | "emergency" => {
+                Some(Level::Fatal)
+            }
+            _ => None,
+        }
+    }
+
+    /// Maps a numeric level. With no scale given, 0-7 are taken as syslog
+    /// severities and 10 and up as bunyan/pino levels.
+    pub fn from_number(n: f64, scale: Option<LevelScale>) -> Option<Level> {
+        let scale = match scale {
+            Some(scale) => scale,
+            None if (0.0..=7.0).contains(&n) => LevelScale::Syslog,
+            None if n >= 10.0 => LevelScale::Bunyan,
+            None => return None,
+        };
+        match scale {
+            LevelScale::Bunyan => {
+                if n < 10.0 {
+                    None
+                } else if n < 20.0 {
+                    Some(Level::Trace)
+                } else if n < 30.0 {
+                    Some(Level::Debug)
+                } else if n < 40.0 {
+                    Some(Level::Info)
+                } else if n < 50.0 {
+                    Some(Level::Warn)
+                } else if n < 60.0 {
+                    Some(Level::Error)
+                } else {
+                    Some(Level::Fatal)
+                }
+            }
+            LevelScale::Syslog => {
+                if n.fract() != 0.0 {
+                    return None;
+                }
+                match n as i64 {
+                    0..=2 => Some(Level::Fatal),
+                    3 => Some(Level::Error),
+                    4 => Some(Level::Warn),
+                    5 | 6 => Some(Level::Info),
+                    7 => Some(Level::Debug),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Trace => "trace",
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+            Level::Fatal => "fatal",
+        }
+    }
+}
+
+/// Conventions for numeric log levels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LevelScale {
+    /// bunyan and pino: 10 trace, 20 debug, 30 info, 40 warn, 50 error, 60 fatal
+    Bunyan,
+    /// syslog severities: 0 emergency through 7 debug
+    Syslog,
+}
+
+impl FromStr for LevelScale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LevelScale, String> {
+        match s {
+            "bunyan" | "pino" => Ok(LevelScale::Bunyan),
+            "syslog" => Ok(LevelScale::Syslog),
+            _ => Err(format!(
+                "unknown level scale `{}`, expected bunyan or syslog",
+                s
+            )),
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Level, String> {
+        Level::from_name(s).ok_or_else(|| format!("unknown log level: {}", s))
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Level, LevelScale};
+
+    #[test]
+    fn level_ordering() {
+        assert!(Level::Trace < Level::Debug);
+        assert!(Level::Info < Level::Warn);
+        assert!(Level::Error < Level::Fatal);
+    }
+
+    #[test]
+    fn level_from_name() {
+        assert_eq!(Level::from_name("INFO"), Some(Level::Info));
+        assert_eq!(Level::from_name("Warning"), Some(Level::Warn));
+        assert_eq!(Level::from_name("err"), Some(Level::Error));
+        assert_eq!(Level::from_name("critical"), Some(Level::Fatal));
+        assert_eq!(Level::from_name("Verbose"), Some(Level::Trace));
+        assert_eq!(Level::from_name("Information"), Some(Level::Info));
+        assert_eq!(Level::from_name("chatty"), None);
+    }
+
+    #[test]
+    fn level_from_str() {
+        assert_eq!("debug".parse::<Level>(), Ok(Level::Debug));
+        assert!("loud".parse::<Level>().is_err());
+    }
+
+    #[test]
+    fn level_from_number_bunyan() {
+        assert_eq!(Level::from_number(10.0, None), Some(Level::Trace));
+        assert_eq!(Level::from_number(30.0, None), Some(Level::Info));
+        assert_eq!(Level::from_number(35.0, None), Some(Level::Info));
+        assert_eq!(Level::from_number(50.0, None), Some(Level::Error));
+        assert_eq!(Level::from_number(60.0, None), Some(Level::Fatal));
+        assert_eq!(Level::from_number(5.0, Some(LevelScale::Bunyan)), None);
+    }
+
+    #[test]
+    fn level_from_number_syslog() {
+        assert_eq!(Level::from_number(0.0, None), Some(Level::Fatal));
+        assert_eq!(Level::from_number(3.0, None), Some(Level::Error));
+        assert_eq!(Level::from_number(4.0, None), Some(Level::Warn));
+        assert_eq!(Level::from_number(6.0, None), Some(Level::Info));
+        assert_eq!(Level::from_number(7.0, None), Some(Level::Debug));
+        assert_eq!(Level::from_number(8.0, None), None);
+        assert_eq!(Level::from_number(30.0, Some(LevelScale::Syslog)), None);
+    }
+}
@@ -1,9 +1,46 @@
+#![allow(
+    clippy::needless_return,
+    clippy::redundant_field_names,
+    clippy::unnecessary_to_owned
+)]
+
+extern crate bzip2;
+extern crate chrono;
+extern crate chrono_tz;
 extern crate colored;
+extern crate flate2;
+extern crate glob;
 extern crate iso8601;
+extern crate regex;
+#[macro_use]
 extern crate serde_json;
+extern crate zstd;
+
+pub mod container;
+pub mod field;
+pub mod filter;
+pub mod follow;
+pub mod input;
+pub mod journal;
+pub mod level;
+pub mod listen;
+pub mod logfmt;
+pub mod prefix;
+pub mod preset;
+pub mod syslog;
+pub mod template;
+pub mod timestamp;
 
+use chrono::{DateTime, FixedOffset, Utc};
 use colored::*;
+use field::{FieldSet, KeyOrder};
+use filter::Filter;
+use level::{Level, LevelScale};
+use preset::Preset;
+use std::borrow::Cow;
 use std::collections::BTreeSet;
+use std::str::FromStr;
+use timestamp::{EpochUnit, Zone};
 
 #[derive(Clone)]
 pub struct Formatter {
@@ -13,6 +50,72 @@ pub struct Formatter {
     pub timestamp_prop: String,
     pub highlight_properties: Vec<String>,
     highlight_properties_set: BTreeSet<String>,
+    pub min_level: Option<Level>,
+    pub keep_unknown_level: bool,
+    pub filter: Option<Filter>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub time_zone: Option<Zone>,
+    pub time_format: Option<String>,
+    pub epoch_unit: Option<EpochUnit>,
+    pub level_scale: Option<LevelScale>,
+    pub level_props: Vec<String>,
+    pub message_props: Vec<String>,
+    pub output: Output,
+    pub key_order: KeyOrder,
+    /// List nested properties under dotted keys
+    pub flatten: bool,
+    /// Properties to list after the headline; all of them if unset
+    pub only: Option<FieldSet>,
+    /// Properties to leave out
+    pub hide: Option<FieldSet>,
+    /// How many levels of JSON inside string values to parse
+    pub unpack_json: u32,
+    /// Read fields out of known prefixes before embedded JSON, rather
+    /// than showing the prefix as it is
+    pub parse_prefix: bool,
+    pub preset: Option<&'static Preset>,
+    /// Pick a preset for each record from the shape of its fields
+    pub detect_preset: bool,
+}
+
+/// The result of reformatting one line of input.
+#[derive(Debug, PartialEq)]
+pub enum Line {
+    /// Output to print.
+    Show(String),
+    /// The line was filtered out.
+    Skip,
+    /// The line was filtered out for being later than `until`.
+    AfterUntil,
+}
+
+/// How records are laid out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Output {
+    /// Everything on one line
+    Line,
+    /// The headline, then one property per line with nested objects as a
+    /// tree
+    Expanded,
+    /// Uncoloured logfmt, for other tools to read
+    Logfmt,
+}
+
+impl FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Output, String> {
+        match s {
+            "line" => Ok(Output::Line),
+            "expanded" => Ok(Output::Expanded),
+            "logfmt" => Ok(Output::Logfmt),
+            _ => Err(format!(
+                "unknown output `{}`, expected line, expanded or logfmt",
+                s
+            )),
+        }
+    }
 }
 
 impl Formatter {
@@ -31,35 +134,420 @@ impl Formatter {
             timestamp_prop: timestamp_prop,
             highlight_properties: highlight_properties,
             highlight_properties_set: prop_set,
+            min_level: None,
+            keep_unknown_level: true,
+            filter: None,
+            since: None,
+            until: None,
+            time_zone: None,
+            time_format: None,
+            epoch_unit: None,
+            level_scale: None,
+            level_props: vec![],
+            message_props: vec![],
+            output: Output::Line,
+            key_order: KeyOrder::Sorted,
+            flatten: false,
+            only: None,
+            hide: None,
+            unpack_json: 0,
+            parse_prefix: false,
+            preset: None,
+            detect_preset: false,
+        }
+    }
+
+    /// Parses a line as JSON, as syslog, as logfmt, or as text followed by
+    /// a JSON object. Returns the record and the text before it, if it's to be
+    /// shown.
+    fn parse(&self, input: &str) -> Result<(serde_json::Value, Option<String>), serde_json::Error> {
+        let err = match serde_json::from_str(input) {
+            Ok(serde_json::Value::Object(obj)) => {
+                if let Some(env) = container::docker(&obj) {
+                    return Ok((self.unwrap_envelope(env), None));
+                }
+                if journal::is_entry(&obj) {
+                    let (message, fields) = journal::unwrap(obj);
+                    return Ok((self.unwrap_message(message, fields), None));
+                }
+                return Ok((serde_json::Value::Object(obj), None));
+            }
+            Ok(val) => return Ok((val, None)),
+            Err(err) => err,
+        };
+        if let Some(env) = container::cri(input) {
+            return Ok((self.unwrap_envelope(env), None));
+        }
+        if let Some((message, fields)) = syslog::parse(input, chrono::Local::now()) {
+            return Ok((self.unwrap_message(message, fields), None));
+        }
+        // Without a priority, a syslog header before JSON is a prefix, only
+        // read with --parse-prefix
+        if prefix::split(input).is_none() {
+            if let Some((message, fields)) = syslog::parse_file_line(input, chrono::Local::now()) {
+                return Ok((self.unwrap_message(message, fields), None));
+            }
+        }
+        if let Some(obj) = logfmt::parse(input).filter(|obj| self.is_logfmt_record(obj)) {
+            return Ok((serde_json::Value::Object(obj), None));
+        }
+        let (text, mut obj) = match prefix::split(input) {
+            Some(split) => split,
+            None => return Err(err),
+        };
+        if self.parse_prefix {
+            if let Some(fields) = prefix::parse(text, chrono::Local::now()) {
+                self.merge_metadata(&mut obj, fields);
+                return Ok((serde_json::Value::Object(obj), None));
+            }
+        }
+        Ok((serde_json::Value::Object(obj), Some(text.to_string())))
+    }
+
+    /// Parses a line into a record ready to format.
+    fn read(&self, input: &str) -> Result<(serde_json::Value, Option<String>), serde_json::Error> {
+        let (val, text) = self.parse(input)?;
+        if self.unpack_json == 0 {
+            return Ok((val, text));
+        }
+        let mut obj = match val {
+            serde_json::Value::Object(obj) => obj,
+            val => return Ok((field::unpack_json(val, self.unpack_json), text)),
+        };
+        // The message, level and timestamp are shown as they were logged
+        let skip: Vec<&str> = self
+            .message_props()
+            .into_iter()
+            .chain(self.level_props())
+            .chain(self.timestamp_props())
+            .collect();
+        for (k, v) in obj.iter_mut() {
+            if !skip.contains(&k.as_str()) {
+                *v = field::unpack_json(v.take(), self.unpack_json);
+            }
+        }
+        Ok((serde_json::Value::Object(obj), text))
+    }
+
+    /// Parses the application log inside a container runtime envelope,
+    /// adding the envelope's time and stream.
+    fn unwrap_envelope(&self, env: container::Envelope) -> serde_json::Value {
+        let mut fields = serde_json::Map::new();
+        fields.insert("time".to_string(), json!(env.time));
+        fields.insert("stream".to_string(), json!(env.stream));
+        self.unwrap_message(serde_json::Value::String(env.log), fields)
+    }
+
+    /// Makes a record from a message that may itself be a record, adding
+    /// fields from around it.
+    fn unwrap_message(
+        &self,
+        message: serde_json::Value,
+        fields: serde_json::Map<String, serde_json::Value>,
+    ) -> serde_json::Value {
+        let parsed = match message {
+            serde_json::Value::String(ref s) => self.parse(s).ok(),
+            _ => None,
+        };
+        let mut obj = match parsed {
+            Some((serde_json::Value::Object(obj), None)) => obj,
+            _ => {
+                let mut obj = serde_json::Map::new();
+                if !message.is_null() {
+                    obj.insert(self.message_props()[0].to_string(), message);
+                }
+                obj
+            }
+        };
+        self.merge_metadata(&mut obj, fields);
+        serde_json::Value::Object(obj)
+    }
+
+    /// Adds fields found outside a record, such as in a prefix, without
+    /// overriding its own. `time` and `level` are only added if it has no
+    /// timestamp or level, and go under the first timestamp or level prop so
+    /// they're read as such.
+    fn merge_metadata(
+        &self,
+        obj: &mut serde_json::Map<String, serde_json::Value>,
+        fields: serde_json::Map<String, serde_json::Value>,
+    ) {
+        let timestamp_props = self.timestamp_props();
+        let level_props = self.level_props();
+        let has_timestamp = timestamp_props
+            .iter()
+            .any(|p| field::lookup(obj, p).is_some());
+        let has_level = level_props.iter().any(|p| field::lookup(obj, p).is_some());
+        for (k, v) in fields {
+            let k = match k.as_str() {
+                "time" if has_timestamp => continue,
+                "time" => timestamp_props[0].to_string(),
+                "level" if has_level => continue,
+                "level" => level_props[0].to_string(),
+                _ => k,
+            };
+            if obj.contains_key(&k) {
+                continue;
+            }
+            obj.insert(k, v);
         }
     }
 
     pub fn reformat_str(&self, input: &str) -> Result<String, serde_json::Error> {
-        return match serde_json::from_str(input) {
-            Ok(val) => {
+        match self.read(input) {
+            Ok((val, text)) => {
                 let v: serde_json::Value = val;
-                let fmt_clone = self.clone();
-                let s: String = v.format(fmt_clone, 0);
-                Ok(s)
+                let fmt = self.for_record(&v);
+                let s: String = v.format(&fmt, 0);
+                Ok(self.with_prefix(text, s))
             }
             Err(err) => Err(err),
-        };
+        }
+    }
+
+    /// Reformats a single input line, applying any configured filters.
+    /// Lines that aren't JSON or logfmt are passed through as-is.
+    pub fn reformat_line(&self, input: &str) -> Line {
+        match self.read(input) {
+            Ok((val, text)) => {
+                let fmt = self.for_record(&val);
+                if let (Some(until), Some(ts)) = (fmt.until, fmt.record_timestamp(&val)) {
+                    if ts > until {
+                        return Line::AfterUntil;
+                    }
+                }
+                if !fmt.accepts(&val) {
+                    return Line::Skip;
+                }
+                Line::Show(self.with_prefix(text, val.format(&fmt, 0)))
+            }
+            Err(_) => {
+                if self.min_level.is_some() && !self.keep_unknown_level {
+                    return Line::Skip;
+                }
+                // There are no fields for a filter expression to match
+                if self.filter.is_some() {
+                    return Line::Skip;
+                }
+                Line::Show(input.trim_end_matches(&['\n', '\r'][..]).to_string())
+            }
+        }
+    }
+
+    /// Whether a parsed record passes the configured filters. Records
+    /// without a timestamp are never excluded by `since` or `until`.
+    pub fn accepts(&self, val: &serde_json::Value) -> bool {
+        if let Some(min_level) = self.min_level {
+            match self.record_level(val) {
+                Some(level) => {
+                    if level < min_level {
+                        return false;
+                    }
+                }
+                None => {
+                    if !self.keep_unknown_level {
+                        return false;
+                    }
+                }
+            }
+        }
+        if self.since.is_some() || self.until.is_some() {
+            if let Some(ts) = self.record_timestamp(val) {
+                if self.since.is_some_and(|since| ts < since) {
+                    return false;
+                }
+                if self.until.is_some_and(|until| ts > until) {
+                    return false;
+                }
+            }
+        }
+        if let Some(ref filter) = self.filter {
+            if !filter.matches(val) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// The formatter to use for one record, with its preset picked if
+    /// detection is on. Only then does it need to be copied.
+    fn for_record(&self, val: &serde_json::Value) -> Cow<'_, Formatter> {
+        if !self.detect_preset || self.preset.is_some() {
+            return Cow::Borrowed(self);
+        }
+        let mut fmt = self.clone();
+        fmt.preset = val.as_object().and_then(preset::detect);
+        Cow::Owned(fmt)
+    }
+
+    // Explicitly configured props win over the preset's, which win over
+    // the defaults.
+
+    fn timestamp_props(&self) -> Vec<&str> {
+        if !self.timestamp_prop.is_empty() {
+            return vec![&self.timestamp_prop];
+        }
+        match self.preset {
+            Some(p) => p.timestamp_props.to_vec(),
+            None => vec!["time", "timestamp", "ts"],
+        }
+    }
+
+    fn level_props(&self) -> Vec<&str> {
+        if !self.level_props.is_empty() {
+            return self.level_props.iter().map(|p| p.as_str()).collect();
+        }
+        match self.preset {
+            Some(p) => p.level_props.to_vec(),
+            None => vec!["level"],
+        }
+    }
+
+    /// Whether parsed logfmt has a timestamp, level or message, so a
+    /// sentence like `Starting server on port=80` isn't taken for a record.
+    fn is_logfmt_record(&self, obj: &serde_json::Map<String, serde_json::Value>) -> bool {
+        self.timestamp_props()
+            .into_iter()
+            .chain(self.level_props())
+            .chain(self.message_props())
+            .any(|p| obj.contains_key(p))
+    }
+
+    fn message_props(&self) -> Vec<&str> {
+        if !self.message_props.is_empty() {
+            return self.message_props.iter().map(|p| p.as_str()).collect();
+        }
+        match self.preset {
+            Some(p) => p.message_props.to_vec(),
+            None => vec!["message", "msg"],
+        }
+    }
+
+    fn template_props(&self) -> &'static [&'static str] {
+        self.preset.map_or(&[], |p| p.template_props)
+    }
+
+    fn caller_props(&self) -> &'static [&'static str] {
+        self.preset.map_or(&[], |p| p.caller_props)
+    }
+
+    fn error_props(&self) -> &'static [&'static str] {
+        self.preset.map_or(&[], |p| p.error_props)
+    }
+
+    /// Fields that are rendered as blocks when they span multiple lines.
+    /// Error objects are checked for a nested `stack` as well.
+    fn block_props(&self) -> Vec<String> {
+        let mut props = vec![];
+        let defaults = ["stack", "stacktrace", "exception", "err", "error"];
+        for prop in defaults.iter().chain(self.error_props()) {
+            for candidate in [prop.to_string(), format!("{}.stack", prop)] {
+                if !props.contains(&candidate) {
+                    props.push(candidate);
+                }
+            }
+        }
+        props
+    }
+
+    fn hidden_props(&self) -> &'static [&'static str] {
+        self.preset.map_or(&[], |p| p.hidden_props)
+    }
+
+    fn default_level(&self, obj: &serde_json::Map<String, serde_json::Value>) -> Option<Level> {
+        let level = self.preset.and_then(|p| p.default_level)?;
+        if self
+            .level_props()
+            .iter()
+            .any(|p| field::lookup(obj, p).is_some())
+        {
+            return None;
+        }
+        Some(level)
+    }
+
+    fn epoch_unit(&self) -> Option<EpochUnit> {
+        self.epoch_unit.or(self.preset.and_then(|p| p.epoch_unit))
+    }
+
+    fn level_scale(&self) -> Option<LevelScale> {
+        self.level_scale.or(self.preset.and_then(|p| p.level_scale))
+    }
+
+    fn record_timestamp(&self, val: &serde_json::Value) -> Option<DateTime<FixedOffset>> {
+        let obj = val.as_object()?;
+        for prop in self.timestamp_props() {
+            let parsed = match field::lookup(obj, prop) {
+                Some(serde_json::Value::String(date_string)) => {
+                    timestamp::parse_timestamp(date_string)
+                }
+                Some(serde_json::Value::Number(n)) => timestamp::from_epoch(n, self.epoch_unit()),
+                _ => None,
+            };
+            if parsed.is_some() {
+                return parsed;
+            }
+        }
+        None
+    }
+
+    fn record_level(&self, val: &serde_json::Value) -> Option<Level> {
+        let obj = val.as_object()?;
+        for prop in self.level_props() {
+            let level = match field::lookup(obj, prop) {
+                Some(serde_json::Value::String(lvl_str)) => Level::from_name(lvl_str),
+                Some(serde_json::Value::Number(n)) => n
+                    .as_f64()
+                    .and_then(|n| Level::from_number(n, self.level_scale())),
+                _ => None,
+            };
+            if level.is_some() {
+                return level;
+            }
+        }
+        self.default_level(obj)
+    }
+
+    fn format_timestamp_value(&self, val: &serde_json::Value) -> Option<String> {
+        match *val {
+            serde_json::Value::String(ref date_string) => {
+                match iso8601::datetime(date_string.as_str()) {
+                    Ok(d) => Some(self.format_timestamp(date_string, timestamp::to_datetime(&d))),
+                    Err(_) => None,
+                }
+            }
+            serde_json::Value::Number(ref n) => timestamp::from_epoch(n, self.epoch_unit())
+                .map(|d| self.format_timestamp(&timestamp::render(&d, None, None), Some(d))),
+            _ => None,
+        }
+    }
+
+    fn format_level_value(&self, val: &serde_json::Value) -> Option<String> {
+        match *val {
+            serde_json::Value::String(ref lvl_str) => self.format_level(lvl_str),
+            serde_json::Value::Number(ref n) => n
+                .as_f64()
+                .and_then(|n| Level::from_number(n, self.level_scale()))
+                .and_then(|lvl| self.format_level(lvl.name())),
+            _ => None,
+        }
     }
 
     fn format_level(&self, level: &str) -> Option<String> {
         let max_len = 5;
-        let mut colorized_level = match level.to_lowercase().as_str() {
-            "trace" => "TRACE".normal(),
-            "debug" => "DEBUG".green(),
-            "info" => " INFO".blue(),
-            "warn" => " WARN".yellow(),
-            "error" => "ERROR".red(),
-            "fatal" => "FATAL".red(),
-            _ => {
+        let mut colorized_level = match Level::from_name(level) {
+            Some(Level::Trace) => "TRACE".normal(),
+            Some(Level::Debug) => "DEBUG".green(),
+            Some(Level::Info) => " INFO".blue(),
+            Some(Level::Warn) => " WARN".yellow(),
+            Some(Level::Error) => "ERROR".red(),
+            Some(Level::Fatal) => "FATAL".red(),
+            None => {
                 let mut lvl_upper = level.to_uppercase();
-                if level.len() > max_len {
-                    lvl_upper = lvl_upper[..max_len].to_string();
-                } else if level.len() < max_len {
+                let len = lvl_upper.chars().count();
+                if len > max_len {
+                    lvl_upper = lvl_upper.chars().take(max_len).collect();
+                } else if len < max_len {
                     lvl_upper = format!("{:>width$}", lvl_upper, width = max_len)
                 }
                 lvl_upper.normal()
@@ -75,17 +563,69 @@ impl Formatter {
         Some(format!("{}: ", colorized_level))
     }
 
-    fn format_timestamp(&self, timestamp: &str) -> String {
+    fn format_timestamp(&self, timestamp: &str, parsed: Option<DateTime<FixedOffset>>) -> String {
+        let mut timestamp = timestamp.to_string();
+        if self.time_zone.is_some() || self.time_format.is_some() {
+            if let Some(d) = parsed {
+                timestamp = timestamp::render(&d, self.time_zone, self.time_format.as_deref());
+            }
+        }
         if self.no_colors {
-            return timestamp.to_string();
+            return timestamp;
         }
         return timestamp.blue().bold().to_string();
     }
 
+    fn with_prefix(&self, text: Option<String>, formatted: String) -> String {
+        let text = match text {
+            Some(text) => text,
+            None => return formatted,
+        };
+        let text = format!("{} ", text.trim_end());
+        if self.no_colors {
+            return text + &formatted;
+        }
+        format!("{}{}", text.dimmed(), formatted)
+    }
+
+    pub fn format_filename(&self, filename: &str) -> String {
+        if self.no_colors {
+            return format!("{}: ", filename);
+        }
+        format!("{}: ", filename.magenta())
+    }
+
+    /// Renders multi-line text, such as a stack trace, as an indented block
+    /// to go below the headline.
+    fn format_block(&self, text: &str) -> String {
+        text.trim_end()
+            .lines()
+            .map(|line| {
+                let line = format!("    {}", line);
+                if self.no_colors {
+                    return line;
+                }
+                line.dimmed().to_string()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn format_caller(&self, caller: &str) -> String {
+        let caller = format!("({})", caller);
+        if self.no_colors {
+            return caller;
+        }
+        caller.dimmed().to_string()
+    }
+
     fn colorize_obj_key(&self, key: &str) -> String {
         if self.no_colors {
             return key.to_string();
         }
+        if self.error_props().contains(&key) {
+            return key.red().underline().to_string();
+        }
         if self.highlight_properties_set.contains(&key.to_string()) {
             return key.yellow().underline().to_string();
         }
@@ -98,30 +638,104 @@ impl Formatter {
         }
         return val.white().to_string();
     }
+
+    /// Renders a record as logfmt. The timestamp, level and message come
+    /// first, under their original keys, and nested objects are flattened.
+    fn format_logfmt(&self, mut fields: serde_json::Map<String, serde_json::Value>) -> String {
+        for prop in self.hidden_props() {
+            fields.shift_remove(*prop);
+        }
+        let mut head = vec![];
+        for props in [
+            self.timestamp_props(),
+            self.level_props(),
+            self.message_props(),
+        ] {
+            for prop in props {
+                if let Some(v) = field::remove(&mut fields, prop) {
+                    head.push((prop.to_string(), v));
+                    break;
+                }
+            }
+        }
+        let mut fields = field::flatten(fields);
+        if let Some(ref only) = self.only {
+            only.only(&mut fields);
+        }
+        if let Some(ref hide) = self.hide {
+            hide.hide(&mut fields);
+        }
+        let keys: Vec<String> = self.key_order.apply(&fields).into_iter().cloned().collect();
+        let rest: Vec<(String, serde_json::Value)> = keys
+            .into_iter()
+            .map(|k| {
+                let v = fields.remove(&k).unwrap_or(serde_json::Value::Null);
+                (k, self.key_order.order_nested(v))
+            })
+            .collect();
+        logfmt::encode(head.iter().chain(&rest).map(|(k, v)| (k.as_str(), v)))
+    }
+
+    /// Renders a property as `key: value` lines for expanded output.
+    fn expand_field(
+        &self,
+        key: &str,
+        val: &serde_json::Value,
+        indent: usize,
+        out: &mut Vec<String>,
+    ) {
+        let pad = "    ".repeat(indent);
+        let key = self.colorize_obj_key(key);
+        match *val {
+            serde_json::Value::Object(ref obj) if !obj.is_empty() => {
+                out.push(format!("{}{}:", pad, key));
+                for k in self.key_order.apply(obj) {
+                    self.expand_field(k, &obj[k], indent + 1, out);
+                }
+            }
+            serde_json::Value::String(ref s) if s.contains('\n') => {
+                out.push(format!("{}{}:", pad, key));
+                for line in s.trim_end().lines() {
+                    out.push(format!("{}    {}", pad, self.colorize_obj_value(line)));
+                }
+            }
+            serde_json::Value::String(ref s) => {
+                out.push(format!("{}{}: {}", pad, key, self.colorize_obj_value(s)))
+            }
+            ref v => out.push(format!(
+                "{}{}: {}",
+                pad,
+                key,
+                self.colorize_obj_value(&self.key_order.order_nested(v.clone()).to_string())
+            )),
+        }
+    }
 }
 
+/// Rendering a record takes it apart, so that properties can be moved out
+/// as they're rendered rather than copied.
 trait Formattable {
-    fn format(&self, fmt: Formatter, depth: u32) -> String;
+    fn format(self, fmt: &Formatter, depth: u32) -> String;
 }
 
 impl Formattable for serde_json::Value {
-    fn format(&self, fmt: Formatter, depth: u32) -> String {
+    fn format(self, fmt: &Formatter, depth: u32) -> String {
         if depth >= fmt.parse_depth {
-            return self.to_string();
+            return fmt.key_order.order_nested(self).to_string();
         }
-        let out = match *self {
-            serde_json::Value::Number(ref l) => l.to_string(),
-            serde_json::Value::Bool(ref l) => l.to_string(),
+        let out = match self {
+            serde_json::Value::Number(l) => l.to_string(),
+            serde_json::Value::Bool(l) => l.to_string(),
             serde_json::Value::Null => String::from("null"),
-            serde_json::Value::String(ref l) => l.to_string(),
-            serde_json::Value::Array(ref arr) => {
+            serde_json::Value::String(l) => l,
+            serde_json::Value::Array(arr) => {
                 let values = arr
-                    .iter()
-                    .map(|item| item.format(fmt.clone(), depth + 1))
+                    .into_iter()
+                    .map(|item| item.format(fmt, depth + 1))
                     .collect::<Vec<String>>();
                 format!("[{}]", values.join(", "))
             }
-            serde_json::Value::Object(ref obj) => obj.format(fmt.clone(), depth + 1),
+            serde_json::Value::Object(obj) => obj.format(fmt, depth + 1),
         };
 
         out
@@ -129,122 +743,190 @@ impl Formattable for serde_json::Value {
 }
 
 impl Formattable for serde_json::Map<String, serde_json::Value> {
-    fn format(&self, fmt: Formatter, depth: u32) -> String {
-        let mut buf = String::new();
-        let mut keys = BTreeSet::new();
-
-        for k in self.keys() {
-            keys.insert(k);
+    fn format(self, fmt: &Formatter, depth: u32) -> String {
+        if fmt.output == Output::Logfmt && depth == 1 {
+            return fmt.format_logfmt(self);
         }
+        let mut buf = String::new();
+        // Fields not yet rendered in the headline
+        let mut fields = self;
+        // CLEF keeps pre-rendered text for formatted template holes in @r,
+        // which is hidden
+        let renderings = fields.get("@r").and_then(|r| r.as_array()).cloned();
 
         let mut has_timestamp = false;
         let mut has_log_level = false;
+        let mut has_caller = false;
         let mut has_message = false;
 
-        // Render timestamp first if present
-        let mut timestamp_props = vec!["time", "timestamp"];
-        if fmt.timestamp_prop != "" {
-            timestamp_props = vec![&fmt.timestamp_prop];
-        }
-        for prop in timestamp_props {
-            let key = String::from(prop);
-            if keys.contains(&key) {
-                let val = self.get(&key);
-                match val {
-                    Some(v) => match v.clone() {
-                        serde_json::Value::String(date_string) => {
-                            let datetime = iso8601::datetime(date_string.as_str());
-                            match datetime {
-                                Ok(_d) => {
-                                    buf.push_str(&format!(
-                                        "[{}] ",
-                                        fmt.format_timestamp(&date_string)
-                                    ));
-                                    keys.remove(&key);
-                                    has_timestamp = true;
-                                }
-                                Err(_) => {}
-                            }
-                        }
-                        _ => {}
-                    },
-                    None => {}
-                }
+        for prop in fmt.hidden_props() {
+            fields.shift_remove(*prop);
+        }
+
+        // Render timestamp first if present. Only the first one found goes
+        // in the headline; any others are listed with the other fields.
+        for prop in fmt.timestamp_props() {
+            let formatted =
+                field::lookup(&fields, prop).and_then(|v| fmt.format_timestamp_value(v));
+            if let Some(formatted) = formatted {
+                buf.push_str(&format!("[{}] ", formatted));
+                field::remove(&mut fields, prop);
+                has_timestamp = true;
+                break;
             }
         }
 
         if !fmt.no_level {
             // Then the log level
-            let level_key = String::from("level");
-            if keys.contains(&level_key) {
-                let val = self.get(&level_key);
-                match val {
-                    Some(v) => match v.clone() {
-                        serde_json::Value::String(lvl_str) => {
-                            let formatted_lvl_str = fmt.format_level(&lvl_str);
-                            match formatted_lvl_str {
-                                Some(s) => {
-                                    buf.push_str(&s);
-                                    keys.remove(&level_key);
-                                    has_log_level = true;
-                                }
-                                None => {}
-                            }
-                        }
-                        _ => {}
-                    },
-                    None => {}
+            for prop in fmt.level_props() {
+                let formatted =
+                    field::lookup(&fields, prop).and_then(|v| fmt.format_level_value(v));
+                if let Some(formatted) = formatted {
+                    buf.push_str(&formatted);
+                    field::remove(&mut fields, prop);
+                    has_log_level = true;
+                    break;
+                }
+            }
+            if !has_log_level {
+                if let Some(formatted) = fmt
+                    .default_level(&fields)
+                    .and_then(|lvl| fmt.format_level(lvl.name()))
+                {
+                    buf.push_str(&formatted);
+                    has_log_level = true;
                 }
             }
         }
 
+        // Then where it was logged from
+        for prop in fmt.caller_props() {
+            let formatted = field::lookup(&fields, prop).and_then(preset::format_caller);
+            if let Some(formatted) = formatted {
+                buf.push_str(&format!("{} ", fmt.format_caller(&formatted)));
+                field::remove(&mut fields, prop);
+                has_caller = true;
+                break;
+            }
+        }
+
         // Then the log message
-        for prop in vec!["message", "msg"] {
-            let key = String::from(prop);
-            if keys.contains(&key) {
-                let val = self.get(&key);
-                match val {
-                    Some(v) => match v.clone() {
-                        serde_json::Value::String(s) => {
-                            buf.push_str(&format!("{} ", s));
-                            keys.remove(&key);
-                            has_message = true;
-                        }
-                        _ => {}
-                    },
-                    None => {}
+        for prop in fmt.message_props() {
+            if let Some(serde_json::Value::String(s)) = field::lookup(&fields, prop) {
+                buf.push_str(&format!("{} ", s));
+                field::remove(&mut fields, prop);
+                has_message = true;
+                break;
+            }
+        }
+        if has_message {
+            // The message already says what a template would
+            for prop in fmt.template_props() {
+                field::remove(&mut fields, prop);
+            }
+        } else {
+            for prop in fmt.template_props() {
+                let rendered = match field::lookup(&fields, prop) {
+                    Some(serde_json::Value::String(s)) => {
+                        template::render(s, &fields, renderings.as_ref())
+                    }
+                    _ => continue,
+                };
+                buf.push_str(&format!("{} ", rendered.text));
+                field::remove(&mut fields, prop);
+                for used in rendered.used {
+                    fields.shift_remove(&used);
+                }
+                has_message = true;
+                break;
+            }
+        }
+
+        // Multi-line stack traces go below the top-level headline
+        let mut blocks = vec![];
+        if depth == 1 {
+            for prop in fmt.block_props() {
+                let text = match field::lookup(&fields, &prop) {
+                    Some(serde_json::Value::String(s)) if s.contains('\n') => s.to_string(),
+                    _ => continue,
+                };
+                blocks.push(fmt.format_block(&text));
+                field::remove(&mut fields, &prop);
+                // A JS stack already starts with the error's message
+                if let Some(parent) = prop.strip_suffix(".stack") {
+                    let message = format!("{}.message", parent);
+                    let repeated = match field::lookup(&fields, &message) {
+                        Some(serde_json::Value::String(m)) => text.contains(m.as_str()),
+                        _ => false,
+                    };
+                    if repeated {
+                        field::remove(&mut fields, &message);
+                    }
                 }
             }
         }
 
-        // Then render the rest of the params
+        // Paths are relative to the record, not to nested objects
+        if depth == 1 {
+            if fmt.flatten {
+                fields = field::flatten(fields);
+            }
+            if let Some(ref only) = fmt.only {
+                only.only(&mut fields);
+            }
+            if let Some(ref hide) = fmt.hide {
+                hide.hide(&mut fields);
+            }
+        }
+
+        // Then render the rest of the params, priority keys first, then errors
+        let keys: Vec<String> = fmt.key_order.apply(&fields).into_iter().cloned().collect();
+        let (priority, keys): (Vec<String>, Vec<String>) =
+            keys.into_iter().partition(|k| fmt.key_order.is_priority(k));
+        let (errors, others): (Vec<String>, Vec<String>) = keys
+            .into_iter()
+            .partition(|k| fmt.error_props().contains(&k.as_str()));
         let mut param_count = 0;
-        for k in keys {
-            let val = self.get(k);
-            match val {
-                Some(v) => {
-                    param_count += 1;
-                    let formatted = v.clone().format(fmt.clone(), depth);
-                    buf.push_str(&format!(
-                        "{k}={v} ",
-                        k = fmt.colorize_obj_key(k),
-                        v = fmt.colorize_obj_value(&formatted),
-                    ));
+        let mut lines = vec![];
+        for k in priority.into_iter().chain(errors).chain(others) {
+            // The order is settled, so removing can reorder what's left
+            if let Some(v) = fields.remove(&k) {
+                if fmt.output == Output::Expanded && depth == 1 {
+                    fmt.expand_field(&k, &v, 1, &mut lines);
+                    continue;
                 }
-                None => {}
+                param_count += 1;
+                let formatted = v.format(fmt, depth);
+                buf.push_str(&format!(
+                    "{k}={v} ",
+                    k = fmt.colorize_obj_key(&k),
+                    v = fmt.colorize_obj_value(&formatted),
+                ));
             }
         }
 
-        if has_timestamp || has_log_level || has_message || param_count > 0 {
+        if has_timestamp || has_log_level || has_caller || has_message || param_count > 0 {
             let strlen = buf.len();
             buf.truncate(strlen - 1);
         }
+        for line in lines.into_iter().chain(blocks) {
+            if !buf.is_empty() {
+                buf.push('\n');
+            }
+            buf.push_str(&line);
+        }
         buf
     }
 }
 
 #[cfg(test)]
 mod tests {
+    /// Tests don't run attached to a terminal, so colours have to be forced
+    /// on where they're being checked.
+    fn force_colors() {
+        super::colored::control::set_override(true);
+    }
+
     fn new_formatter() -> super::Formatter {
         super::Formatter {
             no_colors: false,
@@ -253,6 +935,26 @@ mod tests {
             timestamp_prop: "".to_string(),
             highlight_properties: vec![],
             highlight_properties_set: super::BTreeSet::new(),
+            min_level: None,
+            keep_unknown_level: true,
+            filter: None,
+            since: None,
+            until: None,
+            time_zone: None,
+            time_format: None,
+            epoch_unit: None,
+            level_scale: None,
+            level_props: vec![],
+            message_props: vec![],
+            output: super::Output::Line,
+            key_order: super::KeyOrder::Sorted,
+            flatten: false,
+            only: None,
+            hide: None,
+            unpack_json: 0,
+            parse_prefix: false,
+            preset: None,
+            detect_preset: false,
         }
     }
 
@@ -266,6 +968,7 @@ mod tests {
 
     #[test]
     fn reformat_obj_one_param_color() {
+        force_colors();
         let fmt = new_formatter();
         let a = fmt.reformat_str("{\"a\": 17}").unwrap();
         assert_eq!(a, "\u{1b}[2;4ma\u{1b}[0m=\u{1b}[37m17\u{1b}[0m");
@@ -283,6 +986,7 @@ mod tests {
 
     #[test]
     fn reformat_obj_multiple_params_color() {
+        force_colors();
         let fmt = new_formatter();
         let a = fmt
             .reformat_str("{\"a\": 17, \"c\": 15, \"d\": \"210\"}")
@@ -313,6 +1017,7 @@ mod tests {
 
     #[test]
     fn reformat_obj_with_time_color() {
+        force_colors();
         let fmt = new_formatter();
         let a = fmt
             .reformat_str("{\"time\": \"2018-01-29T00:50:43.176Z\", \"a\": 17}")
@@ -355,158 +1060,1190 @@ mod tests {
     }
 
     #[test]
-    fn reformat_obj_with_timestamp() {
+    fn reformat_obj_with_time_zone() {
         let mut fmt = new_formatter();
         fmt.no_colors = true;
+        fmt.time_zone = Some("America/New_York".parse().unwrap());
         let a = fmt
-            .reformat_str("{\"timestamp\": \"2018-01-29T00:50:43.176Z\", \"a\": 17}")
+            .reformat_str("{\"time\": \"2018-01-29T00:50:43.176Z\", \"a\": 17}")
             .unwrap();
-        assert_eq!(a, "[2018-01-29T00:50:43.176Z] a=17");
+        assert_eq!(a, "[2018-01-28T19:50:43.176-05:00] a=17");
     }
 
     #[test]
-    fn reformat_obj_with_time_no_params() {
-        let fmt = new_formatter();
+    fn reformat_obj_with_time_format() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.time_zone = Some(super::Zone::Utc);
+        fmt.time_format = Some("%H:%M:%S%.3f".to_string());
         let a = fmt
-            .reformat_str("{\"time\": \"2018-01-29T00:50:43.176Z\"}")
+            .reformat_str("{\"time\": \"2018-01-29T00:50:43.176+02:00\", \"a\": 17}")
             .unwrap();
-        assert_eq!(a, "[\u{1b}[1;34m2018-01-29T00:50:43.176Z\u{1b}[0m]");
+        assert_eq!(a, "[22:50:43.176] a=17");
     }
 
     #[test]
-    fn reformat_obj_with_time_and_level_trace() {
-        let fmt = new_formatter();
+    fn reformat_obj_with_time_format_color() {
+        force_colors();
+        let mut fmt = new_formatter();
+        fmt.time_format = Some("%H:%M:%S".to_string());
         let a = fmt
-            .reformat_str(
-                "{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"trace\", \"a\": 17}",
-            )
+            .reformat_str("{\"time\": \"2018-01-29T00:50:43.176Z\"}")
             .unwrap();
-        assert_eq!(
-            a,
-            "[\u{1b}[1;34m2018-01-29T00:50:43.176Z\u{1b}[0m] TRACE: \u{1b}[2;4ma\u{1b}[0m=\u{1b}[37m17\u{1b}[0m"
-        );
+        assert_eq!(a, "[\u{1b}[1;34m00:50:43\u{1b}[0m]");
     }
 
     #[test]
-    fn reformat_obj_with_time_and_level_unknown() {
-        let fmt = new_formatter();
+    fn reformat_obj_with_epoch_millis() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
         let a = fmt
-            .reformat_str(
-                "{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"unknown\", \"a\": 17}",
-            )
+            .reformat_str("{\"time\": 1706489443176, \"level\": 30, \"a\": 17}")
             .unwrap();
-        assert_eq!(
-            a,
-            "[\u{1b}[1;34m2018-01-29T00:50:43.176Z\u{1b}[0m] UNKNO: \u{1b}[2;4ma\u{1b}[0m=\u{1b}[37m17\u{1b}[0m"
-        );
+        assert_eq!(a, "[2024-01-29T00:50:43.176Z]  INFO: a=17");
     }
 
     #[test]
-    fn reformat_obj_with_time_and_level_blank() {
+    fn reformat_obj_with_epoch_seconds_ts() {
         let mut fmt = new_formatter();
         fmt.no_colors = true;
         let a = fmt
-            .reformat_str("{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"\", \"a\": 17}")
+            .reformat_str("{\"ts\": 1706489443.176, \"msg\": \"hello\"}")
             .unwrap();
-        assert_eq!(a, "[2018-01-29T00:50:43.176Z] a=17 level=\"\"");
+        assert_eq!(a, "[2024-01-29T00:50:43.176Z] hello");
     }
 
     #[test]
-    fn reformat_obj_with_time_and_level_short() {
-        let fmt = new_formatter();
+    fn reformat_obj_with_epoch_and_time_zone() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.time_zone = Some("America/New_York".parse().unwrap());
+        fmt.time_format = Some("%H:%M:%S".to_string());
         let a = fmt
-            .reformat_str("{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"sha\", \"a\": 17}")
+            .reformat_str("{\"time\": 1706489443176, \"a\": 17}")
             .unwrap();
-        assert_eq!(
-            a,
-            "[\u{1b}[1;34m2018-01-29T00:50:43.176Z\u{1b}[0m]   SHA: \u{1b}[2;4ma\u{1b}[0m=\u{1b}[37m17\u{1b}[0m"
-        );
+        assert_eq!(a, "[19:50:43] a=17");
     }
 
     #[test]
-    fn reformat_obj_with_time_and_level_debug() {
-        let fmt = new_formatter();
+    fn reformat_obj_with_epoch_explicit_unit() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.epoch_unit = Some(super::EpochUnit::Seconds);
+        let a = fmt.reformat_str("{\"time\": 42, \"a\": 17}").unwrap();
+        assert_eq!(a, "[1970-01-01T00:00:42.000Z] a=17");
+    }
+
+    #[test]
+    fn reformat_obj_with_small_number_time() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let a = fmt.reformat_str("{\"time\": 42, \"a\": 17}").unwrap();
+        assert_eq!(a, "a=17 time=42");
+    }
+
+    #[test]
+    fn reformat_obj_with_time_and_timestamp() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
         let a = fmt
-            .reformat_str(
-                "{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"debug\", \"a\": 17}",
-            )
+            .reformat_str("{\"time\": \"2018-01-29T00:50:43.176Z\", \"timestamp\": \"2018-01-29T00:50:44.000Z\"}")
             .unwrap();
         assert_eq!(
             a,
-            "[\u{1b}[1;34m2018-01-29T00:50:43.176Z\u{1b}[0m] \u{1b}[32mDEBUG\u{1b}[0m: \u{1b}[2;4ma\u{1b}[0m=\u{1b}[37m17\u{1b}[0m"
+            "[2018-01-29T00:50:43.176Z] timestamp=\"2018-01-29T00:50:44.000Z\""
         );
     }
 
     #[test]
-    fn reformat_obj_with_time_and_level_debug_no_colors() {
+    fn reformat_obj_with_timestamp() {
         let mut fmt = new_formatter();
         fmt.no_colors = true;
         let a = fmt
-            .reformat_str(
-                "{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"debug\", \"a\": 17}",
-            )
+            .reformat_str("{\"timestamp\": \"2018-01-29T00:50:43.176Z\", \"a\": 17}")
             .unwrap();
-        assert_eq!(a, "[2018-01-29T00:50:43.176Z] DEBUG: a=17");
+        assert_eq!(a, "[2018-01-29T00:50:43.176Z] a=17");
+    }
+
+    #[test]
+    fn reformat_obj_with_extra_timestamps_in_every_output() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let input = "{\"time\": \"2018-01-29T00:50:43.176Z\", \"timestamp\": \"2018-01-29T00:50:44Z\", \"ts\": 1517187045, \"a\": 17}";
+        assert_eq!(
+            fmt.reformat_str(input).unwrap(),
+            "[2018-01-29T00:50:43.176Z] a=17 timestamp=\"2018-01-29T00:50:44Z\" ts=1517187045"
+        );
+        fmt.output = super::Output::Expanded;
+        assert_eq!(
+            fmt.reformat_str(input).unwrap(),
+            "[2018-01-29T00:50:43.176Z]\n    a: 17\n    timestamp: 2018-01-29T00:50:44Z\n    ts: 1517187045"
+        );
+        fmt.output = super::Output::Logfmt;
+        assert_eq!(
+            fmt.reformat_str(input).unwrap(),
+            "time=2018-01-29T00:50:43.176Z a=17 timestamp=2018-01-29T00:50:44Z ts=1517187045"
+        );
+    }
+
+    #[test]
+    fn reformat_obj_with_time_no_params() {
+        force_colors();
+        let fmt = new_formatter();
+        let a = fmt
+            .reformat_str("{\"time\": \"2018-01-29T00:50:43.176Z\"}")
+            .unwrap();
+        assert_eq!(a, "[\u{1b}[1;34m2018-01-29T00:50:43.176Z\u{1b}[0m]");
+    }
+
+    #[test]
+    fn reformat_obj_with_time_and_level_trace() {
+        force_colors();
+        let fmt = new_formatter();
+        let a = fmt
+            .reformat_str(
+                "{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"trace\", \"a\": 17}",
+            )
+            .unwrap();
+        assert_eq!(
+            a,
+            "[\u{1b}[1;34m2018-01-29T00:50:43.176Z\u{1b}[0m] TRACE: \u{1b}[2;4ma\u{1b}[0m=\u{1b}[37m17\u{1b}[0m"
+        );
+    }
+
+    #[test]
+    fn reformat_obj_with_time_and_level_unknown() {
+        force_colors();
+        let fmt = new_formatter();
+        let a = fmt
+            .reformat_str(
+                "{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"unknown\", \"a\": 17}",
+            )
+            .unwrap();
+        assert_eq!(
+            a,
+            "[\u{1b}[1;34m2018-01-29T00:50:43.176Z\u{1b}[0m] UNKNO: \u{1b}[2;4ma\u{1b}[0m=\u{1b}[37m17\u{1b}[0m"
+        );
+    }
+
+    #[test]
+    fn reformat_obj_with_multibyte_level() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let a = fmt
+            .reformat_str("{\"level\": \"ééééééé\", \"msg\": \"x\"}")
+            .unwrap();
+        assert_eq!(a, "ÉÉÉÉÉ: x");
+        let a = fmt
+            .reformat_str("{\"level\": \"éé\", \"msg\": \"x\"}")
+            .unwrap();
+        assert_eq!(a, "   ÉÉ: x");
+    }
+
+    #[test]
+    fn reformat_obj_with_level_aliases() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        for (level, shown) in &[
+            ("warning", " WARN"),
+            ("WARNING", " WARN"),
+            ("err", "ERROR"),
+            ("critical", "FATAL"),
+            ("crit", "FATAL"),
+            ("panic", "FATAL"),
+            ("notice", " INFO"),
+            ("alert", "FATAL"),
+            ("emerg", "FATAL"),
+            ("emergency", "FATAL"),
+        ] {
+            let input = format!("{{\"level\": \"{}\", \"msg\": \"hi\"}}", level);
+            assert_eq!(fmt.reformat_str(&input).unwrap(), format!("{}: hi", shown));
+        }
+    }
+
+    #[test]
+    fn reformat_obj_with_time_and_level_blank() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let a = fmt
+            .reformat_str("{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"\", \"a\": 17}")
+            .unwrap();
+        assert_eq!(a, "[2018-01-29T00:50:43.176Z] a=17 level=\"\"");
+    }
+
+    #[test]
+    fn reformat_obj_with_time_and_level_short() {
+        force_colors();
+        let fmt = new_formatter();
+        let a = fmt
+            .reformat_str("{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"sha\", \"a\": 17}")
+            .unwrap();
+        assert_eq!(
+            a,
+            "[\u{1b}[1;34m2018-01-29T00:50:43.176Z\u{1b}[0m]   SHA: \u{1b}[2;4ma\u{1b}[0m=\u{1b}[37m17\u{1b}[0m"
+        );
+    }
+
+    #[test]
+    fn reformat_obj_with_time_and_level_debug() {
+        force_colors();
+        let fmt = new_formatter();
+        let a = fmt
+            .reformat_str(
+                "{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"debug\", \"a\": 17}",
+            )
+            .unwrap();
+        assert_eq!(
+            a,
+            "[\u{1b}[1;34m2018-01-29T00:50:43.176Z\u{1b}[0m] \u{1b}[32mDEBUG\u{1b}[0m: \u{1b}[2;4ma\u{1b}[0m=\u{1b}[37m17\u{1b}[0m"
+        );
+    }
+
+    #[test]
+    fn reformat_obj_with_time_and_level_debug_no_colors() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let a = fmt
+            .reformat_str(
+                "{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"debug\", \"a\": 17}",
+            )
+            .unwrap();
+        assert_eq!(a, "[2018-01-29T00:50:43.176Z] DEBUG: a=17");
     }
 
     #[test]
     fn reformat_obj_with_time_and_level_info() {
+        force_colors();
+        let fmt = new_formatter();
+        let a = fmt
+            .reformat_str(
+                "{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"info\", \"a\": 17}",
+            )
+            .unwrap();
+        assert_eq!(
+            a,
+            "[\u{1b}[1;34m2018-01-29T00:50:43.176Z\u{1b}[0m] \u{1b}[34m INFO\u{1b}[0m: \u{1b}[2;4ma\u{1b}[0m=\u{1b}[37m17\u{1b}[0m"
+        );
+    }
+
+    #[test]
+    fn reformat_obj_with_time_and_level_warn() {
+        force_colors();
+        let fmt = new_formatter();
+        let a = fmt
+            .reformat_str(
+                "{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"warn\", \"a\": 17}",
+            )
+            .unwrap();
+        assert_eq!(
+            a,
+            "[\u{1b}[1;34m2018-01-29T00:50:43.176Z\u{1b}[0m] \u{1b}[33m WARN\u{1b}[0m: \u{1b}[2;4ma\u{1b}[0m=\u{1b}[37m17\u{1b}[0m"
+        );
+    }
+
+    #[test]
+    fn reformat_obj_with_time_and_level_error() {
+        force_colors();
+        let fmt = new_formatter();
+        let a = fmt
+            .reformat_str(
+                "{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"error\", \"a\": 17}",
+            )
+            .unwrap();
+        assert_eq!(
+            a,
+            "[\u{1b}[1;34m2018-01-29T00:50:43.176Z\u{1b}[0m] \u{1b}[31mERROR\u{1b}[0m: \u{1b}[2;4ma\u{1b}[0m=\u{1b}[37m17\u{1b}[0m"
+        );
+    }
+
+    #[test]
+    fn reformat_obj_with_time_and_level_fatal() {
+        force_colors();
+        let fmt = new_formatter();
+        let a = fmt
+            .reformat_str(
+                "{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"fatal\", \"a\": 17}",
+            )
+            .unwrap();
+        assert_eq!(
+            a,
+            "[\u{1b}[1;34m2018-01-29T00:50:43.176Z\u{1b}[0m] \u{1b}[31mFATAL\u{1b}[0m: \u{1b}[2;4ma\u{1b}[0m=\u{1b}[37m17\u{1b}[0m"
+        );
+    }
+
+    #[test]
+    fn reformat_obj_with_time_and_level_alias() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let a = fmt
+            .reformat_str(
+                "{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"warning\", \"a\": 17}",
+            )
+            .unwrap();
+        assert_eq!(a, "[2018-01-29T00:50:43.176Z]  WARN: a=17");
+    }
+
+    #[test]
+    fn reformat_line_min_level() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.min_level = Some(super::Level::Warn);
+        assert_eq!(
+            fmt.reformat_line("{\"level\": \"info\", \"a\": 17}"),
+            super::Line::Skip
+        );
+        assert_eq!(
+            fmt.reformat_line("{\"level\": \"warn\", \"a\": 17}"),
+            super::Line::Show(" WARN: a=17".to_string())
+        );
+        assert_eq!(
+            fmt.reformat_line("{\"level\": \"ERROR\", \"a\": 17}"),
+            super::Line::Show("ERROR: a=17".to_string())
+        );
+    }
+
+    #[test]
+    fn reformat_line_min_level_keep_unknown() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.min_level = Some(super::Level::Warn);
+        assert_eq!(
+            fmt.reformat_line("{\"level\": \"chatty\", \"a\": 17}"),
+            super::Line::Show("CHATT: a=17".to_string())
+        );
+        assert_eq!(
+            fmt.reformat_line("{\"a\": 17}"),
+            super::Line::Show("a=17".to_string())
+        );
+        assert_eq!(
+            fmt.reformat_line("not json\n"),
+            super::Line::Show("not json".to_string())
+        );
+    }
+
+    #[test]
+    fn reformat_line_min_level_drop_unknown() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.min_level = Some(super::Level::Warn);
+        fmt.keep_unknown_level = false;
+        assert_eq!(
+            fmt.reformat_line("{\"level\": \"chatty\", \"a\": 17}"),
+            super::Line::Skip
+        );
+        assert_eq!(fmt.reformat_line("{\"a\": 17}"), super::Line::Skip);
+        assert_eq!(fmt.reformat_line("not json\n"), super::Line::Skip);
+    }
+
+    #[test]
+    fn reformat_obj_with_bunyan_level() {
+        force_colors();
         let fmt = new_formatter();
         let a = fmt
-            .reformat_str(
-                "{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"info\", \"a\": 17}",
-            )
+            .reformat_str("{\"level\": 50, \"msg\": \"boom\"}")
+            .unwrap();
+        assert_eq!(a, "\u{1b}[31mERROR\u{1b}[0m: boom");
+    }
+
+    #[test]
+    fn reformat_obj_with_syslog_level() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let a = fmt
+            .reformat_str("{\"level\": 4, \"msg\": \"hmm\"}")
+            .unwrap();
+        assert_eq!(a, " WARN: hmm");
+    }
+
+    #[test]
+    fn reformat_obj_with_level_scale() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.level_scale = Some(super::LevelScale::Syslog);
+        let a = fmt
+            .reformat_str("{\"level\": 30, \"msg\": \"hmm\"}")
+            .unwrap();
+        assert_eq!(a, "hmm level=30");
+    }
+
+    #[test]
+    fn reformat_line_min_level_numeric() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.min_level = Some(super::Level::Warn);
+        assert_eq!(
+            fmt.reformat_line("{\"level\": 30, \"a\": 17}"),
+            super::Line::Skip
+        );
+        assert_eq!(
+            fmt.reformat_line("{\"level\": 40, \"a\": 17}"),
+            super::Line::Show(" WARN: a=17".to_string())
+        );
+        assert_eq!(
+            fmt.reformat_line("{\"level\": 3, \"a\": 17}"),
+            super::Line::Show("ERROR: a=17".to_string())
+        );
+    }
+
+    #[test]
+    fn reformat_obj_with_level_and_message_props() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.level_props = vec!["severity".to_string(), "@l".to_string()];
+        fmt.message_props = vec!["@m".to_string()];
+        let a = fmt
+            .reformat_str("{\"@l\": \"Warning\", \"@m\": \"disk low\", \"level\": \"x\"}")
+            .unwrap();
+        assert_eq!(a, " WARN: disk low level=\"x\"");
+        let a = fmt
+            .reformat_str("{\"severity\": \"ERROR\", \"@l\": \"Warning\"}")
+            .unwrap();
+        assert_eq!(a, "ERROR: @l=\"Warning\"");
+    }
+
+    #[test]
+    fn reformat_obj_with_nested_props() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.level_props = vec!["log.level".to_string()];
+        fmt.timestamp_prop = "@timestamp".to_string();
+        let a = fmt
+            .reformat_str("{\"@timestamp\": \"2018-01-29T00:50:43.176Z\", \"log\": {\"level\": \"info\", \"logger\": \"app\"}, \"message\": \"hi\"}")
+            .unwrap();
+        assert_eq!(
+            a,
+            "[2018-01-29T00:50:43.176Z]  INFO: hi log={\"logger\":\"app\"}"
+        );
+    }
+
+    #[test]
+    fn reformat_obj_with_flat_dotted_prop() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.level_props = vec!["log.level".to_string()];
+        let a = fmt
+            .reformat_str("{\"log.level\": \"error\", \"message\": \"hi\"}")
+            .unwrap();
+        assert_eq!(a, "ERROR: hi");
+    }
+
+    #[test]
+    fn reformat_line_min_level_with_level_props() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.min_level = Some(super::Level::Warn);
+        fmt.level_props = vec!["severity".to_string()];
+        assert_eq!(
+            fmt.reformat_line("{\"severity\": \"INFO\", \"a\": 17}"),
+            super::Line::Skip
+        );
+    }
+
+    #[test]
+    fn reformat_obj_with_zap_preset() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.preset = super::preset::find("zap");
+        let a = fmt
+            .reformat_str("{\"level\": \"error\", \"ts\": 1706489443.176, \"caller\": \"main.go:42\", \"msg\": \"boom\", \"a\": 17, \"stacktrace\": \"main.run\"}")
+            .unwrap();
+        assert_eq!(
+            a,
+            "[2024-01-29T00:50:43.176Z] ERROR: (main.go:42) boom stacktrace=\"main.run\" a=17"
+        );
+    }
+
+    #[test]
+    fn reformat_obj_with_bunyan_preset() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.preset = super::preset::find("bunyan");
+        let a = fmt
+            .reformat_str("{\"name\": \"app\", \"hostname\": \"h\", \"pid\": 1, \"level\": 5, \"msg\": \"hi\", \"time\": \"2018-01-29T00:50:43.176Z\", \"v\": 0}")
+            .unwrap();
+        // 5 isn't a bunyan level, so it isn't read as syslog either
+        assert_eq!(a, "[2018-01-29T00:50:43.176Z] hi level=5 name=\"app\"");
+    }
+
+    #[test]
+    fn reformat_obj_with_slog_source() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.detect_preset = true;
+        let a = fmt
+            .reformat_str("{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"WARN\", \"source\": {\"function\": \"main.run\", \"file\": \"main.go\", \"line\": 42}, \"msg\": \"hmm\"}")
+            .unwrap();
+        assert_eq!(a, "[2018-01-29T00:50:43.176Z]  WARN: (main.go:42) hmm");
+    }
+
+    #[test]
+    fn reformat_obj_with_detected_structlog() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.detect_preset = true;
+        let a = fmt
+            .reformat_str("{\"timestamp\": \"2018-01-29T00:50:43.176Z\", \"level\": \"info\", \"event\": \"user logged in\", \"user\": \"bob\"}")
+            .unwrap();
+        assert_eq!(
+            a,
+            "[2018-01-29T00:50:43.176Z]  INFO: user logged in user=\"bob\""
+        );
+    }
+
+    #[test]
+    fn reformat_obj_preset_overridden_by_props() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.preset = super::preset::find("structlog");
+        fmt.message_props = vec!["msg".to_string()];
+        let a = fmt
+            .reformat_str("{\"msg\": \"hi\", \"event\": \"login\"}")
+            .unwrap();
+        assert_eq!(a, "hi event=\"login\"");
+    }
+
+    #[test]
+    fn reformat_line_min_level_with_detected_pino() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.detect_preset = true;
+        fmt.min_level = Some(super::Level::Warn);
+        assert_eq!(
+            fmt.reformat_line("{\"level\": 30, \"time\": 1706489443176, \"msg\": \"hi\"}"),
+            super::Line::Skip
+        );
+        assert_eq!(
+            fmt.reformat_line("{\"level\": 40, \"time\": 1706489443176, \"msg\": \"hi\"}"),
+            super::Line::Show("[2024-01-29T00:50:43.176Z]  WARN: hi".to_string())
+        );
+    }
+
+    #[test]
+    fn reformat_obj_with_error_prop_color() {
+        force_colors();
+        let mut fmt = new_formatter();
+        fmt.preset = super::preset::find("zerolog");
+        let a = fmt.reformat_str("{\"error\": \"EOF\"}").unwrap();
+        assert_eq!(a, "\u{1b}[4;31merror\u{1b}[0m=\u{1b}[37m\"EOF\"\u{1b}[0m");
+    }
+
+    #[test]
+    fn reformat_obj_with_clef_template() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.detect_preset = true;
+        let a = fmt
+            .reformat_str("{\"@t\": \"2018-01-29T00:50:43.176Z\", \"@mt\": \"User {UserId} logged in after {Elapsed:0.0} ms\", \"@r\": [\"12.3\"], \"UserId\": 42, \"Elapsed\": 12.345, \"SourceContext\": \"Auth\"}")
+            .unwrap();
+        assert_eq!(
+            a,
+            "[2018-01-29T00:50:43.176Z]  INFO: User 42 logged in after 12.3 ms SourceContext=\"Auth\""
+        );
+    }
+
+    #[test]
+    fn reformat_obj_with_clef_message_and_template() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.detect_preset = true;
+        let a = fmt
+            .reformat_str("{\"@t\": \"2018-01-29T00:50:43.176Z\", \"@m\": \"hello 5\", \"@mt\": \"hello {n}\", \"n\": 5}")
+            .unwrap();
+        assert_eq!(a, "[2018-01-29T00:50:43.176Z]  INFO: hello 5 n=5");
+    }
+
+    #[test]
+    fn reformat_obj_with_clef_exception() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.preset = super::preset::find("clef");
+        let a = fmt
+            .reformat_str("{\"@t\": \"2018-01-29T00:50:43.176Z\", \"@l\": \"Error\", \"@m\": \"Failed\", \"@i\": \"a1b2c3d4\", \"@x\": \"System.Exception: boom\\n   at Program.Main()\\n\"}")
+            .unwrap();
+        assert_eq!(
+            a,
+            "[2018-01-29T00:50:43.176Z] ERROR: Failed\n    System.Exception: boom\n       at Program.Main()"
+        );
+    }
+
+    #[test]
+    fn reformat_obj_with_exception_block_color() {
+        force_colors();
+        let mut fmt = new_formatter();
+        fmt.preset = super::preset::find("clef");
+        let a = fmt
+            .reformat_str("{\"@l\": \"Verbose\", \"@x\": \"a\\nb\"}")
+            .unwrap();
+        assert_eq!(
+            a,
+            "TRACE:\n\u{1b}[2m    a\u{1b}[0m\n\u{1b}[2m    b\u{1b}[0m"
+        );
+    }
+
+    #[test]
+    fn reformat_line_min_level_with_clef_default() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.preset = super::preset::find("clef");
+        fmt.min_level = Some(super::Level::Warn);
+        assert_eq!(
+            fmt.reformat_line("{\"@t\": \"2018-01-29T00:50:43.176Z\", \"@mt\": \"hi\"}"),
+            super::Line::Skip
+        );
+        assert_eq!(
+            fmt.reformat_line("{\"@l\": \"Warning\", \"@mt\": \"hi\"}"),
+            super::Line::Show(" WARN: hi".to_string())
+        );
+    }
+
+    #[test]
+    fn reformat_obj_with_stack_block() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let a = fmt
+            .reformat_str("{\"level\": \"error\", \"msg\": \"failed\", \"stack\": \"at foo\\n    at bar\", \"a\": 17}")
+            .unwrap();
+        assert_eq!(a, "ERROR: failed a=17\n    at foo\n        at bar");
+    }
+
+    #[test]
+    fn reformat_obj_with_single_line_stack() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let a = fmt
+            .reformat_str("{\"msg\": \"failed\", \"stack\": \"at foo\"}")
+            .unwrap();
+        assert_eq!(a, "failed stack=\"at foo\"");
+    }
+
+    #[test]
+    fn reformat_obj_with_nested_error_stack() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let a = fmt
+            .reformat_str("{\"level\": 50, \"msg\": \"request failed\", \"err\": {\"type\": \"Error\", \"message\": \"boom\", \"stack\": \"Error: boom\\n    at handler (app.js:10:5)\"}}")
+            .unwrap();
+        assert_eq!(
+            a,
+            "ERROR: request failed err={\"type\":\"Error\"}\n    Error: boom\n        at handler (app.js:10:5)"
+        );
+    }
+
+    #[test]
+    fn reformat_obj_with_multiple_blocks() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let a = fmt
+            .reformat_str("{\"exception\": \"a\\nb\", \"stack\": \"c\\nd\", \"error\": {\"message\": \"x\", \"stack\": \"e\\nf\"}}")
+            .unwrap();
+        assert_eq!(
+            a,
+            "error={\"message\":\"x\"}\n    c\n    d\n    a\n    b\n    e\n    f"
+        );
+    }
+
+    #[test]
+    fn reformat_obj_with_key_order() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let input = "{\"msg\": \"hi\", \"zone\": \"eu\", \"user\": \"bob\", \"a\": 17, \"request_id\": \"r1\"}";
+        fmt.key_order = super::KeyOrder::Original;
+        assert_eq!(
+            fmt.reformat_str(input).unwrap(),
+            "hi zone=\"eu\" user=\"bob\" a=17 request_id=\"r1\""
+        );
+        fmt.key_order = "priority:request_id,user".parse().unwrap();
+        assert_eq!(
+            fmt.reformat_str(input).unwrap(),
+            "hi request_id=\"r1\" user=\"bob\" a=17 zone=\"eu\""
+        );
+    }
+
+    #[test]
+    fn reformat_obj_with_nested_key_order() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let input = "{\"msg\": \"hi\", \"nested\": {\"z\": 1, \"a\": 2}}";
+        assert_eq!(
+            fmt.reformat_str(input).unwrap(),
+            "hi nested={\"a\":2,\"z\":1}"
+        );
+        fmt.key_order = super::KeyOrder::Original;
+        assert_eq!(
+            fmt.reformat_str(input).unwrap(),
+            "hi nested={\"z\":1,\"a\":2}"
+        );
+    }
+
+    #[test]
+    fn reformat_obj_with_priority_before_errors() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.preset = super::preset::find("zerolog");
+        fmt.key_order = "priority:user".parse().unwrap();
+        let input = "{\"message\": \"hi\", \"error\": \"boom\", \"user\": \"bob\", \"a\": 1}";
+        assert_eq!(
+            fmt.reformat_str(input).unwrap(),
+            "hi user=\"bob\" error=\"boom\" a=1"
+        );
+    }
+
+    #[test]
+    fn reformat_obj_with_only_and_hide() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let input = "{\"level\": \"info\", \"msg\": \"hi\", \"pid\": 1, \"user\": \"bob\", \"k8s\": {\"pod\": \"web\", \"ns\": \"prod\"}}";
+        fmt.hide = Some(super::FieldSet::new(&["pid".to_string(), "k8s.*".to_string()]).unwrap());
+        assert_eq!(fmt.reformat_str(input).unwrap(), " INFO: hi user=\"bob\"");
+        fmt.hide = None;
+        fmt.only = Some(super::FieldSet::new(&["k8s.pod".to_string()]).unwrap());
+        assert_eq!(
+            fmt.reformat_str(input).unwrap(),
+            " INFO: hi k8s={\"pod\":\"web\"}"
+        );
+    }
+
+    #[test]
+    fn reformat_line_hide_doesnt_affect_filter() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.hide = Some(super::FieldSet::new(&["status".to_string()]).unwrap());
+        fmt.filter = Some(super::Filter::parse("status == 500").unwrap());
+        assert_eq!(
+            fmt.reformat_line("{\"status\": 500, \"a\": 1}"),
+            super::Line::Show("a=1".to_string())
+        );
+    }
+
+    #[test]
+    fn reformat_obj_flattened() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.flatten = true;
+        let a = fmt
+            .reformat_str("{\"msg\": \"done\", \"http\": {\"request\": {\"method\": \"GET\"}, \"response\": {\"status\": 200}}, \"nested\": {\"prop1\": 5}}")
             .unwrap();
         assert_eq!(
             a,
-            "[\u{1b}[1;34m2018-01-29T00:50:43.176Z\u{1b}[0m] \u{1b}[34m INFO\u{1b}[0m: \u{1b}[2;4ma\u{1b}[0m=\u{1b}[37m17\u{1b}[0m"
+            "done http.request.method=\"GET\" http.response.status=200 nested.prop1=5"
         );
     }
 
     #[test]
-    fn reformat_obj_with_time_and_level_warn() {
-        let fmt = new_formatter();
+    fn reformat_obj_flattened_highlight_and_hide() {
+        force_colors();
+        let mut fmt = new_formatter();
+        fmt.flatten = true;
+        fmt.highlight_properties_set
+            .insert("http.status".to_string());
+        fmt.hide = Some(super::FieldSet::new(&["http.method".to_string()]).unwrap());
         let a = fmt
-            .reformat_str(
-                "{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"warn\", \"a\": 17}",
-            )
+            .reformat_str("{\"http\": {\"status\": 200, \"method\": \"GET\"}}")
+            .unwrap();
+        assert_eq!(a, "\u{1b}[4;33mhttp.status\u{1b}[0m=\u{1b}[37m200\u{1b}[0m");
+    }
+
+    #[test]
+    fn reformat_obj_expanded() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.output = super::Output::Expanded;
+        let a = fmt
+            .reformat_str("{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"error\", \"msg\": \"request failed\", \"user\": \"bob\", \"http\": {\"status\": 500, \"request\": {\"method\": \"GET\"}}, \"tags\": [\"a\", 1], \"note\": \"two\\nlines\", \"stack\": \"at foo\\nat bar\"}")
             .unwrap();
         assert_eq!(
             a,
-            "[\u{1b}[1;34m2018-01-29T00:50:43.176Z\u{1b}[0m] \u{1b}[33m WARN\u{1b}[0m: \u{1b}[2;4ma\u{1b}[0m=\u{1b}[37m17\u{1b}[0m"
+            "[2018-01-29T00:50:43.176Z] ERROR: request failed
+    http:
+        request:
+            method: GET
+        status: 500
+    note:
+        two
+        lines
+    tags: [\"a\",1]
+    user: bob
+    at foo
+    at bar"
         );
     }
 
     #[test]
-    fn reformat_obj_with_time_and_level_error() {
-        let fmt = new_formatter();
+    fn reformat_obj_expanded_color() {
+        force_colors();
+        let mut fmt = new_formatter();
+        fmt.output = super::Output::Expanded;
+        let a = fmt.reformat_str("{\"msg\": \"hi\", \"a\": 17}").unwrap();
+        assert_eq!(a, "hi\n    \u{1b}[2;4ma\u{1b}[0m: \u{1b}[37m17\u{1b}[0m");
+    }
+
+    #[test]
+    fn reformat_obj_expanded_no_headline() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.output = super::Output::Expanded;
+        let a = fmt.reformat_str("{\"a\": 17, \"b\": {}}").unwrap();
+        assert_eq!(a, "    a: 17\n    b: {}");
+    }
+
+    #[test]
+    fn reformat_obj_logfmt() {
+        let mut fmt = new_formatter();
+        fmt.output = super::Output::Logfmt;
         let a = fmt
-            .reformat_str(
-                "{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"error\", \"a\": 17}",
-            )
+            .reformat_str("{\"user\": 5, \"msg\": \"hello world\", \"level\": \"info\", \"time\": \"2018-01-29T00:50:43.176Z\", \"http\": {\"path\": \"/a b\"}, \"q\": \"x=\\\"y\\\"\"}")
             .unwrap();
         assert_eq!(
             a,
-            "[\u{1b}[1;34m2018-01-29T00:50:43.176Z\u{1b}[0m] \u{1b}[31mERROR\u{1b}[0m: \u{1b}[2;4ma\u{1b}[0m=\u{1b}[37m17\u{1b}[0m"
+            "time=2018-01-29T00:50:43.176Z level=info msg=\"hello world\" http.path=\"/a b\" q=\"x=\\\"y\\\"\" user=5"
         );
     }
 
     #[test]
-    fn reformat_obj_with_time_and_level_fatal() {
-        let fmt = new_formatter();
+    fn reformat_obj_logfmt_with_preset_and_hide() {
+        let mut fmt = new_formatter();
+        fmt.output = super::Output::Logfmt;
+        fmt.preset = super::preset::find("bunyan");
+        fmt.hide = Some(super::FieldSet::new(&["name".to_string()]).unwrap());
         let a = fmt
             .reformat_str(
-                "{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"fatal\", \"a\": 17}",
+                "{\"v\": 0, \"pid\": 1, \"level\": 30, \"msg\": \"hi\", \"name\": \"app\"}",
             )
             .unwrap();
+        assert_eq!(a, "level=30 msg=hi");
+    }
+
+    #[test]
+    fn reformat_logfmt_input() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let a = fmt
+            .reformat_str("time=2018-01-29T00:50:43.176Z level=info msg=\"hello world\" user=5")
+            .unwrap();
+        assert_eq!(a, "[2018-01-29T00:50:43.176Z]  INFO: hello world user=5");
+        let a = fmt.reformat_str("level=info msg=hi debug").unwrap();
+        assert_eq!(a, " INFO: hi debug=true");
+        assert_eq!(
+            fmt.reformat_line("Starting server on port=80\n"),
+            super::Line::Show("Starting server on port=80".to_string())
+        );
+        assert_eq!(
+            fmt.reformat_line("a=1 b=2\n"),
+            super::Line::Show("a=1 b=2".to_string())
+        );
+    }
+
+    #[test]
+    fn reformat_line_logfmt_filtered() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.min_level = Some(super::Level::Warn);
+        fmt.filter = Some(super::Filter::parse("user == 5").unwrap());
+        assert_eq!(
+            fmt.reformat_line("level=info msg=hi user=5\n"),
+            super::Line::Skip
+        );
+        assert_eq!(
+            fmt.reformat_line("level=error msg=boom user=6\n"),
+            super::Line::Skip
+        );
+        assert_eq!(
+            fmt.reformat_line("level=error msg=boom user=5\n"),
+            super::Line::Show("ERROR: boom user=5".to_string())
+        );
+    }
+
+    #[test]
+    fn reformat_obj_after_prefix() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let a = fmt
+            .reformat_str("web_1  | {\"level\": \"info\", \"msg\": \"hi\"}")
+            .unwrap();
+        assert_eq!(a, "web_1  |  INFO: hi");
+    }
+
+    #[test]
+    fn reformat_obj_after_prefix_color() {
+        force_colors();
+        let fmt = new_formatter();
+        let a = fmt.reformat_str("app: {\"msg\": \"hi\"}").unwrap();
+        assert_eq!(a, "\u{1b}[2mapp: \u{1b}[0mhi");
+    }
+
+    #[test]
+    fn reformat_obj_with_parsed_prefix() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.parse_prefix = true;
+        let a = fmt
+            .reformat_str("Jan 29 00:50:43 web1 app[123]: {\"time\": \"2018-01-29T00:50:43.176Z\", \"msg\": \"hi\", \"host\": \"h\"}")
+            .unwrap();
         assert_eq!(
             a,
-            "[\u{1b}[1;34m2018-01-29T00:50:43.176Z\u{1b}[0m] \u{1b}[31mFATAL\u{1b}[0m: \u{1b}[2;4ma\u{1b}[0m=\u{1b}[37m17\u{1b}[0m"
+            "[2018-01-29T00:50:43.176Z] hi app=\"app\" host=\"h\" pid=123"
+        );
+        let a = fmt.reformat_str("web_1  | {\"msg\": \"hi\"}").unwrap();
+        assert_eq!(a, "hi service=\"web_1\"");
+        // Unknown prefixes are still shown
+        let a = fmt.reformat_str("[worker] {\"msg\": \"hi\"}").unwrap();
+        assert_eq!(a, "[worker] hi");
+    }
+
+    #[test]
+    fn reformat_line_after_prefix_filtered() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.filter = Some(super::Filter::parse("a == 1").unwrap());
+        assert_eq!(fmt.reformat_line("x | {\"a\": 2}\n"), super::Line::Skip);
+        assert_eq!(
+            fmt.reformat_line("x | {\"a\": 1}\n"),
+            super::Line::Show("x | a=1".to_string())
+        );
+    }
+
+    #[test]
+    fn reformat_docker_envelope() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let a = fmt
+            .reformat_str("{\"log\":\"{\\\"level\\\":\\\"info\\\",\\\"msg\\\":\\\"hi\\\"}\\n\",\"stream\":\"stdout\",\"time\":\"2024-01-29T00:50:43.123Z\"}")
+            .unwrap();
+        assert_eq!(a, "[2024-01-29T00:50:43.123Z]  INFO: hi stream=\"stdout\"");
+        let a = fmt
+            .reformat_str("{\"log\":\"plain text\\n\",\"stream\":\"stderr\",\"time\":\"2024-01-29T00:50:43.123Z\"}")
+            .unwrap();
+        assert_eq!(a, "[2024-01-29T00:50:43.123Z] plain text stream=\"stderr\"");
+    }
+
+    #[test]
+    fn reformat_cri_line() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let a = fmt
+            .reformat_str("2024-01-29T00:50:43.123Z stdout F {\"time\": \"2024-01-29T00:50:42.000Z\", \"level\": \"warn\", \"msg\": \"slow\"}")
+            .unwrap();
+        assert_eq!(
+            a,
+            "[2024-01-29T00:50:42.000Z]  WARN: slow stream=\"stdout\""
+        );
+    }
+
+    #[test]
+    fn reformat_line_cri_filtered() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.filter = Some(super::Filter::parse("stream == \"stderr\"").unwrap());
+        assert_eq!(
+            fmt.reformat_line("2024-01-29T00:50:43.123Z stdout F hello\n"),
+            super::Line::Skip
+        );
+        assert_eq!(
+            fmt.reformat_line("2024-01-29T00:50:43.123Z stderr F hello\n"),
+            super::Line::Show("[2024-01-29T00:50:43.123Z] hello stream=\"stderr\"".to_string())
+        );
+    }
+
+    #[test]
+    fn reformat_journal_entry() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let a = fmt
+            .reformat_str("{\"__CURSOR\": \"s=1\", \"__REALTIME_TIMESTAMP\": \"1706489443176123\", \"PRIORITY\": \"4\", \"_SYSTEMD_UNIT\": \"nginx.service\", \"_PID\": \"42\", \"MESSAGE\": \"upstream slow\"}")
+            .unwrap();
+        assert_eq!(
+            a,
+            "[2024-01-29T00:50:43.176Z]  WARN: upstream slow _PID=\"42\" unit=\"nginx.service\""
+        );
+    }
+
+    #[test]
+    fn reformat_journal_json_message() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let a = fmt
+            .reformat_str("{\"__REALTIME_TIMESTAMP\": \"1706489443176123\", \"PRIORITY\": \"6\", \"_SYSTEMD_UNIT\": \"api.service\", \"MESSAGE\": \"{\\\"level\\\": \\\"error\\\", \\\"msg\\\": \\\"boom\\\", \\\"user\\\": 5}\"}")
+            .unwrap();
+        assert_eq!(
+            a,
+            "[2024-01-29T00:50:43.176Z] ERROR: boom unit=\"api.service\" user=5"
+        );
+    }
+
+    #[test]
+    fn reformat_line_journal_min_level() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.min_level = Some(super::Level::Warn);
+        assert_eq!(
+            fmt.reformat_line("{\"__REALTIME_TIMESTAMP\": \"1706489443176123\", \"PRIORITY\": \"6\", \"MESSAGE\": \"hi\"}"),
+            super::Line::Skip
+        );
+    }
+
+    #[test]
+    fn reformat_line_journal_custom_props() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.min_level = Some(super::Level::Warn);
+        fmt.level_props = vec!["severity".to_string()];
+        fmt.timestamp_prop = "ts.wall".to_string();
+        let input = "{\"__REALTIME_TIMESTAMP\": \"1706489443176123\", \"PRIORITY\": \"6\", \"MESSAGE\": \"hi\"}";
+        assert_eq!(fmt.reformat_line(input), super::Line::Skip);
+        assert_eq!(
+            fmt.reformat_line(&input.replace("\"6\"", "\"3\"")),
+            super::Line::Show("[2024-01-29T00:50:43.176Z] ERROR: hi".to_string())
+        );
+    }
+
+    #[test]
+    fn reformat_syslog_5424() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let a = fmt
+            .reformat_str("<165>1 2003-10-11T22:14:15.003Z web01 api 123 - [req@1 id=\"7\"] listening on :80\n")
+            .unwrap();
+        assert_eq!(
+            a,
+            "[2003-10-11T22:14:15.003Z]  INFO: listening on :80 app=\"api\" facility=\"local4\" host=\"web01\" pid=123 req@1={\"id\":\"7\"}"
+        );
+    }
+
+    #[test]
+    fn reformat_syslog_json_message() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let a = fmt
+            .reformat_str("<14>1 2003-10-11T22:14:15.003Z web01 api - - - {\"level\": \"error\", \"msg\": \"boom\", \"user\": 5}")
+            .unwrap();
+        assert_eq!(
+            a,
+            "[2003-10-11T22:14:15.003Z] ERROR: boom app=\"api\" facility=\"user\" host=\"web01\" user=5"
+        );
+    }
+
+    #[test]
+    fn reformat_line_syslog_3164_min_level() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.min_level = Some(super::Level::Warn);
+        assert_eq!(
+            fmt.reformat_line("<14>Oct 11 22:14:15 web01 cron[9]: job done"),
+            super::Line::Skip
+        );
+        match fmt.reformat_line("<11>Oct 11 22:14:15 web01 cron[9]: job failed") {
+            super::Line::Show(s) => assert!(s.ends_with(
+                "ERROR: job failed app=\"cron\" facility=\"user\" host=\"web01\" pid=9"
+            )),
+            _ => panic!("expected the line to be shown"),
+        }
+    }
+
+    #[test]
+    fn reformat_syslog_file_line() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        match fmt.reformat_line("Oct 11 22:14:15 web01 cron[9]: level=warn msg=\"job slow\"\n") {
+            super::Line::Show(s) => {
+                assert!(s.ends_with(" WARN: job slow app=\"cron\" host=\"web01\" pid=9"))
+            }
+            _ => panic!("expected the line to be shown"),
+        }
+        // JSON after the header is still a prefix
+        let a = fmt
+            .reformat_str("Oct 11 22:14:15 web01 cron[9]: {\"msg\": \"hi\"}")
+            .unwrap();
+        assert_eq!(a, "Oct 11 22:14:15 web01 cron[9]: hi");
+    }
+
+    #[test]
+    fn reformat_obj_with_unpacked_json() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let input = "{\"msg\": \"req\", \"body\": \"{\\\"user\\\": 5}\"}";
+        assert_eq!(
+            fmt.reformat_str(input).unwrap(),
+            "req body=\"{\\\"user\\\": 5}\""
+        );
+        fmt.unpack_json = 1;
+        assert_eq!(fmt.reformat_str(input).unwrap(), "req body={\"user\":5}");
+        fmt.flatten = true;
+        assert_eq!(fmt.reformat_str(input).unwrap(), "req body.user=5");
+    }
+
+    #[test]
+    fn reformat_obj_with_unpacked_json_keeps_message() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.unpack_json = 1;
+        let input = "{\"msg\": \"{\\\"a\\\":1}\", \"level\": \"info\", \"b\": \"[1]\"}";
+        assert_eq!(fmt.reformat_str(input).unwrap(), " INFO: {\"a\":1} b=[1]");
+    }
+
+    #[test]
+    fn reformat_line_filter_unpacked_json() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.unpack_json = 1;
+        fmt.filter = Some(super::Filter::parse("body.user == 5").unwrap());
+        assert_eq!(
+            fmt.reformat_line("{\"body\": \"{\\\"user\\\": 6}\"}"),
+            super::Line::Skip
+        );
+        assert_eq!(
+            fmt.reformat_line("{\"body\": \"{\\\"user\\\": 5}\"}"),
+            super::Line::Show("body={\"user\":5}".to_string())
+        );
+    }
+
+    #[test]
+    fn reformat_line_filter() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        fmt.filter = Some(super::Filter::parse("status >= 500 && user.id == 42").unwrap());
+        assert_eq!(
+            fmt.reformat_line("{\"status\": 503, \"user\": {\"id\": 42}}"),
+            super::Line::Show("status=503 user={\"id\":42}".to_string())
+        );
+        assert_eq!(
+            fmt.reformat_line("{\"status\": 200, \"user\": {\"id\": 42}}"),
+            super::Line::Skip
+        );
+        assert_eq!(fmt.reformat_line("not json\n"), super::Line::Skip);
+    }
+
+    #[test]
+    fn reformat_line_since_until() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        let now = super::Utc::now();
+        fmt.since = Some(super::timestamp::parse_bound("2018-01-29T00:00:00Z", now).unwrap());
+        fmt.until = Some(super::timestamp::parse_bound("2018-01-29T01:00:00Z", now).unwrap());
+        assert_eq!(
+            fmt.reformat_line("{\"time\": \"2018-01-28T23:59:59Z\", \"a\": 17}"),
+            super::Line::Skip
+        );
+        assert_eq!(
+            fmt.reformat_line("{\"time\": \"2018-01-29T00:50:43.176Z\", \"a\": 17}"),
+            super::Line::Show("[2018-01-29T00:50:43.176Z] a=17".to_string())
+        );
+        assert_eq!(
+            fmt.reformat_line("{\"time\": \"2018-01-29T01:50:43+01:00\", \"a\": 17}"),
+            super::Line::Show("[2018-01-29T01:50:43+01:00] a=17".to_string())
+        );
+        assert_eq!(
+            fmt.reformat_line("{\"time\": \"2018-01-29T01:00:01Z\", \"a\": 17}"),
+            super::Line::AfterUntil
+        );
+        assert_eq!(
+            fmt.reformat_line("{\"a\": 17}"),
+            super::Line::Show("a=17".to_string())
         );
     }
 
     #[test]
     fn reformat_obj_with_time_message_and_level() {
+        force_colors();
         let fmt = new_formatter();
         let a = fmt.reformat_str(
             "{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"fatal\", \"message\": \"it's burning\"}",
@@ -519,6 +2256,7 @@ mod tests {
 
     #[test]
     fn reformat_obj_with_time_message_attr_and_level() {
+        force_colors();
         let fmt = new_formatter();
         let a = fmt.reformat_str(
             "{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"fatal\", \"message\": \"something is on fire!\", \"a\": 17}",
@@ -572,6 +2310,7 @@ mod tests {
 
     #[test]
     fn reformat_obj_with_time_message_attrs_and_level() {
+        force_colors();
         let fmt = new_formatter();
         let a = fmt.reformat_str(
             "{\"time\": \"2018-01-29T00:50:43.176Z\", \"level\": \"fatal\", \"message\": \"something is on fire!\", \"a\": 17, \"b\": 18}",
@@ -584,6 +2323,7 @@ mod tests {
 
     #[test]
     fn reformat_obj_with_time_message_attrs_and_level_highlight_property() {
+        force_colors();
         let mut fmt = new_formatter();
         fmt.highlight_properties_set = super::BTreeSet::new();
         fmt.highlight_properties_set.insert("b".to_string());
@@ -598,6 +2338,7 @@ mod tests {
 
     #[test]
     fn reformat_obj_with_time_message_attrs_and_level_highlight_properties() {
+        force_colors();
         let mut fmt = new_formatter();
         fmt.highlight_properties_set = super::BTreeSet::new();
         fmt.highlight_properties_set.insert("a".to_string());
@@ -611,6 +2352,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn format_filename_no_colors() {
+        let mut fmt = new_formatter();
+        fmt.no_colors = true;
+        assert_eq!(fmt.format_filename("app.log"), "app.log: ");
+    }
+
+    #[test]
+    fn format_filename_color() {
+        force_colors();
+        let fmt = new_formatter();
+        assert_eq!(
+            fmt.format_filename("app.log"),
+            "\u{1b}[35mapp.log\u{1b}[0m: "
+        );
+    }
+
     #[test]
     fn reformat_null() {
         let fmt = new_formatter();
@@ -0,0 +1,450 @@
+//! Receiving log lines from other processes over UDP, TCP and Unix domain
+//! sockets, so log shippers can be pointed straight at structy.
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read};
+use std::net::{SocketAddr, TcpListener, UdpSocket};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+use std::time::Duration;
+
+/// The largest UDP datagram there can be.
+const MAX_DATAGRAM: usize = 65_536;
+
+/// The longest line read from a connection. Longer lines are split, so a
+/// client that never sends a newline can't use up memory.
+const MAX_LINE: usize = 1 << 20;
+
+/// How many received lines can wait to be formatted before the sockets stop
+/// being read, so a client sending faster than the output is written slows
+/// down instead of using up memory.
+const QUEUE_LEN: usize = 1024;
+
+/// The longest pause between retries of a socket that keeps failing.
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// A socket to receive lines on.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Endpoint {
+    Udp(SocketAddr),
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Endpoint::Udp(addr) => write!(f, "udp://{}", addr),
+            Endpoint::Tcp(addr) => write!(f, "tcp://{}", addr),
+            Endpoint::Unix(ref path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Something received by a listener.
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// A line, without its newline.
+    Line(&'a str),
+    /// A socket couldn't be read. It's retried after a pause that grows
+    /// while the error persists, and the error isn't reported again until a
+    /// read succeeds.
+    Error(&'a Endpoint, io::Error),
+}
+
+enum Message {
+    Line(String),
+    Error(Endpoint, io::Error),
+    Close,
+}
+
+/// Listens on a set of sockets at once, merging the lines received on all
+/// of them. Each UDP datagram holds one or more lines; TCP and Unix socket
+/// connections are streams of newline-delimited lines. Unix socket files
+/// are removed when the listener is dropped.
+pub struct Listener {
+    endpoints: Vec<Endpoint>,
+    messages: Receiver<Message>,
+    closer: Closer,
+}
+
+/// Stops a listener's `run` from another thread.
+#[derive(Clone)]
+pub struct Closer(SyncSender<Message>);
+
+impl Closer {
+    pub fn close(&self) {
+        let _ = self.0.send(Message::Close);
+    }
+}
+
+impl Listener {
+    /// Binds every endpoint, failing if any of them can't be. A Unix socket
+    /// left behind by an earlier run is replaced, but not one that something
+    /// is still listening on.
+    pub fn bind(endpoints: &[Endpoint]) -> io::Result<Listener> {
+        let (sender, messages) = mpsc::sync_channel(QUEUE_LEN);
+        let mut listener = Listener {
+            endpoints: vec![],
+            messages,
+            closer: Closer(sender.clone()),
+        };
+        for endpoint in endpoints {
+            // Anything bound so far is cleaned up when `listener` is dropped
+            let endpoint = bind(endpoint, sender.clone())
+                .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", endpoint, err)))?;
+            listener.endpoints.push(endpoint);
+        }
+        Ok(listener)
+    }
+
+    /// The endpoints being listened on, with any port 0 replaced by the port
+    /// that was picked.
+    pub fn endpoints(&self) -> &[Endpoint] {
+        &self.endpoints
+    }
+
+    /// A handle to stop `run` with, such as from a signal handler.
+    pub fn closer(&self) -> Closer {
+        self.closer.clone()
+    }
+
+    /// Calls `on_event` with every line received and every socket error,
+    /// until closed.
+    pub fn run<F: FnMut(Event)>(&self, mut on_event: F) {
+        while let Ok(message) = self.messages.recv() {
+            match message {
+                Message::Line(line) => on_event(Event::Line(&line)),
+                Message::Error(endpoint, err) => on_event(Event::Error(&endpoint, err)),
+                Message::Close => return,
+            }
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        for endpoint in &self.endpoints {
+            if let Endpoint::Unix(ref path) = *endpoint {
+                let _ = ::std::fs::remove_file(path);
+            }
+        }
+    }
+}
+
+/// Pauses after consecutive errors, twice as long each time, so a socket
+/// that keeps failing doesn't spin. Only the first error is reported.
+struct Backoff {
+    endpoint: Endpoint,
+    delay: Option<Duration>,
+}
+
+impl Backoff {
+    fn new(endpoint: Endpoint) -> Backoff {
+        Backoff {
+            endpoint,
+            delay: None,
+        }
+    }
+
+    fn succeeded(&mut self) {
+        self.delay = None;
+    }
+
+    /// Reports the error if it's the first in a row and waits, returning
+    /// false if nothing is listening any more.
+    fn failed(&mut self, err: io::Error, messages: &SyncSender<Message>) -> bool {
+        let delay = match self.delay {
+            Some(delay) => (delay * 2).min(MAX_BACKOFF),
+            None => {
+                let message = Message::Error(self.endpoint.clone(), err);
+                if messages.send(message).is_err() {
+                    return false;
+                }
+                Duration::from_millis(50)
+            }
+        };
+        self.delay = Some(delay);
+        thread::sleep(delay);
+        true
+    }
+}
+
+fn bind(endpoint: &Endpoint, lines: SyncSender<Message>) -> io::Result<Endpoint> {
+    match *endpoint {
+        Endpoint::Udp(addr) => {
+            let socket = UdpSocket::bind(addr)?;
+            let local = Endpoint::Udp(socket.local_addr()?);
+            let backoff = Backoff::new(local.clone());
+            thread::spawn(move || receive_datagrams(socket, lines, backoff));
+            Ok(local)
+        }
+        Endpoint::Tcp(addr) => {
+            let listener = TcpListener::bind(addr)?;
+            let local = Endpoint::Tcp(listener.local_addr()?);
+            let backoff = Backoff::new(local.clone());
+            thread::spawn(move || accept(listener.incoming(), lines, backoff));
+            Ok(local)
+        }
+        Endpoint::Unix(ref path) => bind_unix(path, lines),
+    }
+}
+
+#[cfg(unix)]
+fn bind_unix(path: &PathBuf, lines: SyncSender<Message>) -> io::Result<Endpoint> {
+    use std::fs;
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    if let Ok(meta) = fs::symlink_metadata(path) {
+        if meta.file_type().is_socket() {
+            // Only a socket nothing is listening on is safe to replace
+            match UnixStream::connect(path) {
+                Ok(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        "another process is listening on this socket",
+                    ))
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::ConnectionRefused => {
+                    fs::remove_file(path)?
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+    let listener = UnixListener::bind(path)?;
+    let backoff = Backoff::new(Endpoint::Unix(path.clone()));
+    thread::spawn(move || accept(listener.incoming(), lines, backoff));
+    Ok(Endpoint::Unix(path.clone()))
+}
+
+#[cfg(not(unix))]
+fn bind_unix(_path: &PathBuf, _lines: SyncSender<Message>) -> io::Result<Endpoint> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "Unix domain sockets aren't supported on this platform",
+    ))
+}
+
+fn receive_datagrams(socket: UdpSocket, lines: SyncSender<Message>, mut backoff: Backoff) {
+    let mut buf = vec![0; MAX_DATAGRAM];
+    loop {
+        let n = match socket.recv_from(&mut buf) {
+            Ok((n, _)) => n,
+            Err(err) => {
+                if !backoff.failed(err, &lines) {
+                    return;
+                }
+                continue;
+            }
+        };
+        backoff.succeeded();
+        for line in String::from_utf8_lossy(&buf[..n]).lines() {
+            if line.is_empty() {
+                continue;
+            }
+            if lines.send(Message::Line(line.to_string())).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Reads each connection to a stream socket on a thread of its own.
+fn accept<I, S>(incoming: I, lines: SyncSender<Message>, mut backoff: Backoff)
+where
+    I: Iterator<Item = io::Result<S>>,
+    S: Read + Send + 'static,
+{
+    for stream in incoming {
+        match stream {
+            Ok(stream) => {
+                backoff.succeeded();
+                let lines = lines.clone();
+                thread::spawn(move || read_stream(stream, lines));
+            }
+            Err(err) => {
+                if !backoff.failed(err, &lines) {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Sends each line of a connection, including a last one without a newline.
+/// Lines longer than `MAX_LINE` bytes are sent in pieces.
+fn read_stream<R: Read>(stream: R, lines: SyncSender<Message>) {
+    let mut reader = BufReader::new(stream);
+    let mut line = vec![];
+    loop {
+        line.clear();
+        match (&mut reader)
+            .take(MAX_LINE as u64)
+            .read_until(b'\n', &mut line)
+        {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(&['\n', '\r'][..]);
+        if text.is_empty() {
+            continue;
+        }
+        if lines.send(Message::Line(text.to_string())).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_stream, Backoff, Endpoint, Listener, Message, MAX_LINE};
+    use std::io::{self, Cursor, Write};
+    use std::net::{TcpStream, UdpSocket};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    fn recv(listener: &Listener) -> String {
+        match listener.messages.recv_timeout(Duration::from_secs(5)) {
+            Ok(Message::Line(line)) => line,
+            Ok(_) => panic!("not a line"),
+            Err(err) => panic!("no line received: {}", err),
+        }
+    }
+
+    #[test]
+    fn listen_udp() {
+        let listener = Listener::bind(&[Endpoint::Udp("127.0.0.1:0".parse().unwrap())]).unwrap();
+        let addr = match listener.endpoints()[0] {
+            Endpoint::Udp(addr) => addr,
+            _ => unreachable!(),
+        };
+        assert_ne!(addr.port(), 0);
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .send_to(b"<14>Oct 11 22:14:15 host app: hi", addr)
+            .unwrap();
+        assert_eq!(recv(&listener), "<14>Oct 11 22:14:15 host app: hi");
+        socket.send_to(b"{\"a\":1}\n{\"b\":2}\n", addr).unwrap();
+        assert_eq!(recv(&listener), "{\"a\":1}");
+        assert_eq!(recv(&listener), "{\"b\":2}");
+    }
+
+    #[test]
+    fn listen_tcp() {
+        let listener = Listener::bind(&[Endpoint::Tcp("127.0.0.1:0".parse().unwrap())]).unwrap();
+        let addr = match listener.endpoints()[0] {
+            Endpoint::Tcp(addr) => addr,
+            _ => unreachable!(),
+        };
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"{\"a\":1}\r\n\n{\"b\":").unwrap();
+        assert_eq!(recv(&listener), "{\"a\":1}");
+        stream.write_all(b"2}").unwrap();
+        drop(stream);
+        assert_eq!(recv(&listener), "{\"b\":2}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn listen_unix() {
+        use std::fs::{self, File};
+        use std::os::unix::net::UnixStream;
+
+        let dir = ::std::env::temp_dir().join(format!("structy-listen-{}", ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("structy.sock");
+        let listener = Listener::bind(&[Endpoint::Unix(path.clone())]).unwrap();
+        assert!(path.exists());
+        drop(listener);
+        assert!(!path.exists());
+
+        // A socket left behind is replaced
+        ::std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let listener = Listener::bind(&[Endpoint::Unix(path.clone())]).unwrap();
+        let mut stream = UnixStream::connect(&path).unwrap();
+        stream.write_all(b"level=info msg=hi\n").unwrap();
+        assert_eq!(recv(&listener), "level=info msg=hi");
+
+        // One that's still being listened on isn't
+        let err = Listener::bind(&[Endpoint::Unix(path.clone())])
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ::std::io::ErrorKind::AddrInUse);
+        stream.write_all(b"level=info msg=still here\n").unwrap();
+        assert_eq!(recv(&listener), "level=info msg=still here");
+
+        // Anything other than a socket is left alone
+        let file = dir.join("file");
+        File::create(&file).unwrap();
+        assert!(Listener::bind(&[Endpoint::Unix(file)]).is_err());
+    }
+
+    #[test]
+    fn read_stream_splits_long_lines() {
+        let (sender, messages) = mpsc::sync_channel(4);
+        let mut data = vec![b'a'; MAX_LINE + 3];
+        data.extend_from_slice(b"\nb");
+        read_stream(Cursor::new(data), sender);
+        let lengths: Vec<usize> = messages
+            .iter()
+            .map(|message| match message {
+                Message::Line(line) => line.len(),
+                _ => panic!("not a line"),
+            })
+            .collect();
+        assert_eq!(lengths, vec![MAX_LINE, 3, 1]);
+    }
+
+    #[test]
+    fn listen_until_closed() {
+        let listener = Listener::bind(&[Endpoint::Udp("127.0.0.1:0".parse().unwrap())]).unwrap();
+        let closer = listener.closer();
+        closer.close();
+        let mut events = 0;
+        listener.run(|_| events += 1);
+        assert_eq!(events, 0);
+    }
+
+    #[test]
+    fn backoff_reports_first_error() {
+        let (sender, messages) = mpsc::sync_channel(4);
+        let mut backoff = Backoff::new(Endpoint::Udp("127.0.0.1:1".parse().unwrap()));
+        let err = || io::Error::other("broken");
+        assert!(backoff.failed(err(), &sender));
+        assert!(backoff.failed(err(), &sender));
+        assert_eq!(backoff.delay, Some(Duration::from_millis(100)));
+        backoff.succeeded();
+        assert!(backoff.failed(err(), &sender));
+        drop(sender);
+        let errors: Vec<String> = messages
+            .iter()
+            .map(|message| match message {
+                Message::Error(endpoint, err) => format!("{}: {}", endpoint, err),
+                _ => panic!("not an error"),
+            })
+            .collect();
+        assert_eq!(errors, vec!["udp://127.0.0.1:1: broken"; 2]);
+
+        // Nobody is listening any more
+        let (sender, messages) = mpsc::sync_channel(4);
+        drop(messages);
+        assert!(!Backoff::new(Endpoint::Unix("x".into())).failed(err(), &sender));
+    }
+
+    #[test]
+    fn endpoint_display() {
+        assert_eq!(
+            Endpoint::Udp("127.0.0.1:5514".parse().unwrap()).to_string(),
+            "udp://127.0.0.1:5514"
+        );
+        assert_eq!(
+            Endpoint::Unix("/tmp/structy.sock".into()).to_string(),
+            "unix:/tmp/structy.sock"
+        );
+    }
+}
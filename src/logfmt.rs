@@ -0,0 +1,255 @@
+//! Reading and writing logfmt, e.g. `level=info msg="hello world" user=5`.
+
+use serde_json::{Map, Value};
+
+/// Parses a logfmt line. Unquoted numbers, booleans and `null` keep
+/// their types; everything else is a string, and a key without a value is
+/// `true`. Returns `None` unless the whole line is keys and `key=value`
+/// pairs, with at least one pair.
+pub fn parse(line: &str) -> Option<Map<String, Value>> {
+    let mut obj = Map::new();
+    let mut pairs = 0;
+    let mut chars = line.trim_end_matches(&['\n', '\r'][..]).chars().peekable();
+    loop {
+        while chars.peek() == Some(&' ') || chars.peek() == Some(&'\t') {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+        let mut key = String::new();
+        while let Some(&c) = chars.peek() {
+            if c == '=' || c <= ' ' || c == '"' {
+                break;
+            }
+            key.push(c);
+            chars.next();
+        }
+        if key.is_empty() {
+            return None;
+        }
+        match chars.next() {
+            Some('=') => pairs += 1,
+            None | Some(' ') | Some('\t') => {
+                obj.insert(key, Value::Bool(true));
+                continue;
+            }
+            _ => return None,
+        }
+        let val = if chars.peek() == Some(&'"') {
+            chars.next();
+            let mut s = String::new();
+            loop {
+                match chars.next()? {
+                    '"' => break,
+                    '\\' => match chars.next()? {
+                        'n' => s.push('\n'),
+                        'r' => s.push('\r'),
+                        't' => s.push('\t'),
+                        'u' => {
+                            let hex: String = chars.by_ref().take(4).collect();
+                            s.push(
+                                u32::from_str_radix(&hex, 16)
+                                    .ok()
+                                    .and_then(::std::char::from_u32)?,
+                            );
+                        }
+                        c => s.push(c),
+                    },
+                    c => s.push(c),
+                }
+            }
+            if chars.peek().is_some_and(|c| *c != ' ' && *c != '\t') {
+                return None;
+            }
+            Value::String(s)
+        } else {
+            let mut s = String::new();
+            while let Some(&c) = chars.peek() {
+                if c == ' ' || c == '\t' {
+                    break;
+                }
+                s.push(c);
+                chars.next();
+            }
+            match ::serde_json::from_str(&s) {
+                Ok(v @ Value::Number(_)) | Ok(v @ Value::Bool(_)) | Ok(v @ Value::Null) => v,
+                _ => Value::String(s),
+            }
+        };
+        obj.insert(key, val);
+    }
+    if pairs == 0 {
+        return None;
+    }
+    Some(obj)
+}
+
+/// Encodes key/value pairs as one logfmt line. Strings are quoted when
+/// they're empty or contain spaces, `=`, quotes or control characters;
+/// objects and arrays are written as quoted JSON.
+pub fn encode<'a, I>(pairs: I) -> String
+where
+    I: IntoIterator<Item = (&'a str, &'a Value)>,
+{
+    pairs
+        .into_iter()
+        .map(|(key, val)| format!("{}={}", encode_key(key), encode_value(val)))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Keys can't be quoted, so characters that would end one are replaced.
+fn encode_key(key: &str) -> String {
+    if key.is_empty() {
+        return "_".to_string();
+    }
+    key.chars()
+        .map(|c| {
+            if c <= ' ' || c == '=' || c == '"' {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+fn encode_value(val: &Value) -> String {
+    match *val {
+        Value::String(ref s) => encode_str(s),
+        Value::Array(_) | Value::Object(_) => encode_str(&val.to_string()),
+        ref v => v.to_string(),
+    }
+}
+
+fn encode_str(s: &str) -> String {
+    let needs_quotes = s.is_empty()
+        || s.chars()
+            .any(|c| c <= ' ' || c == '=' || c == '"' || c == '\\');
+    if !needs_quotes {
+        return s.to_string();
+    }
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode, parse};
+    use serde_json::Value;
+
+    fn enc(pairs: &[(&str, Value)]) -> String {
+        encode(pairs.iter().map(|(k, v)| (*k, v)))
+    }
+
+    #[test]
+    fn encode_plain() {
+        assert_eq!(
+            enc(&[
+                ("level", json!("info")),
+                ("user", json!(5)),
+                ("ok", json!(true)),
+                ("none", json!(null))
+            ]),
+            "level=info user=5 ok=true none=null"
+        );
+    }
+
+    #[test]
+    fn encode_quotes_and_escapes() {
+        assert_eq!(
+            enc(&[
+                ("msg", json!("hello world")),
+                ("q", json!("say \"hi\"")),
+                ("eq", json!("a=b")),
+                ("empty", json!("")),
+                ("nl", json!("a\nb\\c")),
+                ("bell", json!("\u{7}"))
+            ]),
+            "msg=\"hello world\" q=\"say \\\"hi\\\"\" eq=\"a=b\" empty=\"\" nl=\"a\\nb\\\\c\" bell=\"\\u0007\""
+        );
+    }
+
+    #[test]
+    fn encode_keys_and_json() {
+        assert_eq!(
+            enc(&[
+                ("a key", json!([1, "x"])),
+                ("", json!({"b": 1})),
+                ("k=v", json!(1))
+            ]),
+            "a_key=\"[1,\\\"x\\\"]\" _=\"{\\\"b\\\":1}\" k_v=1"
+        );
+    }
+
+    #[test]
+    fn parse_pairs() {
+        let obj = parse("time=2018-01-29T00:50:43.176Z level=info msg=\"hello world\" user=5 ok=true ratio=0.5 id=\"5\" empty=\n").unwrap();
+        assert_eq!(
+            Value::Object(obj),
+            json!({
+                "time": "2018-01-29T00:50:43.176Z",
+                "level": "info",
+                "msg": "hello world",
+                "user": 5,
+                "ok": true,
+                "ratio": 0.5,
+                "id": "5",
+                "empty": ""
+            })
+        );
+    }
+
+    #[test]
+    fn parse_escapes() {
+        let obj = parse("msg=\"say \\\"hi\\\"\\nbye\" path=a=b bell=\"\\u0007\"").unwrap();
+        assert_eq!(
+            Value::Object(obj),
+            json!({"msg": "say \"hi\"\nbye", "path": "a=b", "bell": "\u{7}"})
+        );
+    }
+
+    #[test]
+    fn parse_bare_keys() {
+        let obj = parse("msg=hi debug\tverbose").unwrap();
+        assert_eq!(
+            Value::Object(obj),
+            json!({"msg": "hi", "debug": true, "verbose": true})
+        );
+    }
+
+    #[test]
+    fn parse_rejects_text() {
+        assert_eq!(parse("not json"), None);
+        assert_eq!(parse("msg=\"unterminated"), None);
+        assert_eq!(parse("a=\"b\"c"), None);
+        assert_eq!(parse("=1"), None);
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("{"), None);
+    }
+
+    #[test]
+    fn parse_round_trip() {
+        let obj = json!({"msg": "a \"b\" = c\n", "n": 1, "k": "v"});
+        let line = encode(
+            obj.as_object()
+                .unwrap()
+                .iter()
+                .map(|(k, v)| (k.as_str(), v)),
+        );
+        assert_eq!(Value::Object(parse(&line).unwrap()), obj);
+    }
+}
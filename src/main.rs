@@ -1,15 +1,33 @@
+extern crate chrono;
 #[macro_use]
 extern crate clap;
-extern crate colored;
-extern crate serde_json;
+#[cfg(unix)]
+extern crate signal_hook;
+extern crate structy;
 
-mod lib;
-
-use clap::{App, Arg};
-use std::io;
+use chrono::{DateTime, Utc};
+use clap::{App, Arg, ArgGroup, ArgMatches, SubCommand};
+use std::collections::HashMap;
+use std::io::{self, BufRead};
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::process;
+use std::thread;
+use structy::container::Reassembler;
+use structy::field::{FieldSet, KeyOrder};
+use structy::filter::Filter;
+use structy::follow::{Event, Follower};
+use structy::input::{self, Compression, Source};
+use structy::level::{Level, LevelScale};
+use structy::listen::{self, Closer, Endpoint, Listener};
+use structy::preset;
+use structy::timestamp::{self, EpochUnit, Zone};
+use structy::{Formatter, Line, Output};
 
 fn main() {
+    let mut preset_names = vec!["auto"];
+    preset_names.extend(preset::names());
+
     let matches = App::new("structy")
         .about("JSON structured logging parser")
         .version("v0.2.0")
@@ -18,6 +36,7 @@ fn main() {
                 .long("no-colors")
                 .short("n")
                 .required(false)
+                .global(true)
                 .help("Disable colorization"),
         )
         .arg(
@@ -25,6 +44,7 @@ fn main() {
                 .long("no-level")
                 .short("l")
                 .required(false)
+                .global(true)
                 .help("Disable log level highlighting"),
         )
         .arg(
@@ -32,6 +52,7 @@ fn main() {
                 .long("parse-depth")
                 .short("d")
                 .required(false)
+                .global(true)
                 .takes_value(true)
                 .help("Number of levels deep to parse JSON"),
         )
@@ -40,18 +61,245 @@ fn main() {
                 .long("timestamp-prop")
                 .short("t")
                 .required(false)
+                .global(true)
                 .takes_value(true)
                 .help("Property to use as a timestamp"),
         )
+        .arg(
+            Arg::with_name("level_properties")
+                .long("level-prop")
+                .required(false)
+                .global(true)
+                .multiple(true)
+                .number_of_values(1)
+                .use_delimiter(true)
+                .takes_value(true)
+                .help("Properties to use as the log level, in order of preference (default: level)"),
+        )
+        .arg(
+            Arg::with_name("message_properties")
+                .long("message-prop")
+                .required(false)
+                .global(true)
+                .multiple(true)
+                .number_of_values(1)
+                .use_delimiter(true)
+                .takes_value(true)
+                .help("Properties to use as the log message, in order of preference (default: message, msg)"),
+        )
+        .arg(
+            Arg::with_name("unpack_json")
+                .long("unpack-json")
+                .required(false)
+                .global(true)
+                .takes_value(true)
+                .value_name("depth")
+                .help("Parse string values holding JSON objects or arrays, up to this many levels deep"),
+        )
+        .arg(
+            Arg::with_name("parse_prefix")
+                .long("parse-prefix")
+                .required(false)
+                .global(true)
+                .help("Turn docker-compose and syslog prefixes before JSON into properties instead of showing them"),
+        )
+        .arg(
+            Arg::with_name("preset")
+                .long("preset")
+                .required(false)
+                .global(true)
+                .takes_value(true)
+                .possible_values(&preset_names)
+                .help("Use the field conventions of a logging library, or auto to detect them per record"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .short("o")
+                .required(false)
+                .global(true)
+                .takes_value(true)
+                .possible_values(&["line", "expanded", "logfmt"])
+                .help("Print each record on one line, expanded with one property per line, or as logfmt [default: line]"),
+        )
+        .arg(
+            Arg::with_name("key_order")
+                .long("key-order")
+                .required(false)
+                .global(true)
+                .takes_value(true)
+                .help("Order of the remaining properties: sorted, original or priority:<k1,k2,...> [default: sorted]"),
+        )
+        .arg(
+            Arg::with_name("flatten")
+                .long("flatten")
+                .required(false)
+                .global(true)
+                .help("List nested properties under dotted keys, e.g. http.response.status=200"),
+        )
+        .arg(
+            Arg::with_name("only")
+                .long("only")
+                .required(false)
+                .global(true)
+                .multiple(true)
+                .number_of_values(1)
+                .use_delimiter(true)
+                .takes_value(true)
+                .help("Only list these properties after the message; dotted paths and globs like k8s.* work"),
+        )
+        .arg(
+            Arg::with_name("hide")
+                .long("hide")
+                .required(false)
+                .global(true)
+                .multiple(true)
+                .number_of_values(1)
+                .use_delimiter(true)
+                .takes_value(true)
+                .help("Leave these properties out; dotted paths and globs like k8s.* work"),
+        )
         .arg(
             Arg::with_name("highlight_properties")
                 .long("highlight-props")
                 .short("h")
                 .required(false)
+                .global(true)
                 .multiple(true)
                 .takes_value(true)
                 .help("Properties to highlight"),
         )
+        .arg(
+            Arg::with_name("with_filename")
+                .long("with-filename")
+                .short("H")
+                .required(false)
+                .help("Prefix each line with the name of the file it was read from"),
+        )
+        .arg(
+            Arg::with_name("follow")
+                .long("follow")
+                .short("f")
+                .required(false)
+                .help("Keep reading files as they grow, reopening them when rotated"),
+        )
+        .arg(
+            Arg::with_name("min_level")
+                .long("min-level")
+                .required(false)
+                .global(true)
+                .takes_value(true)
+                .help("Only show records at or above this level (trace, debug, info, warn, error, fatal)"),
+        )
+        .arg(
+            Arg::with_name("unknown_level")
+                .long("unknown-level")
+                .required(false)
+                .global(true)
+                .takes_value(true)
+                .possible_values(&["keep", "drop"])
+                .help("Whether --min-level keeps or drops records without a recognised level [default: keep]"),
+        )
+        .arg(
+            Arg::with_name("level_scale")
+                .long("level-scale")
+                .required(false)
+                .global(true)
+                .takes_value(true)
+                .possible_values(&["auto", "bunyan", "pino", "syslog"])
+                .help("How to read numeric levels: bunyan/pino (10-60) or syslog (0-7) [default: auto]"),
+        )
+        .arg(
+            Arg::with_name("filter")
+                .long("filter")
+                .required(false)
+                .global(true)
+                .takes_value(true)
+                .help("Only show records matching an expression, e.g. 'status >= 500 && path =~ \"^/api\"'"),
+        )
+        .arg(
+            Arg::with_name("since")
+                .long("since")
+                .required(false)
+                .global(true)
+                .takes_value(true)
+                .help("Only show records at or after a time, e.g. 2018-01-29T00:50:43Z or 15m (ago)"),
+        )
+        .arg(
+            Arg::with_name("until")
+                .long("until")
+                .required(false)
+                .global(true)
+                .takes_value(true)
+                .help("Only show records at or before a time; stops reading time-sorted files early"),
+        )
+        .arg(
+            Arg::with_name("epoch_unit")
+                .long("epoch-unit")
+                .required(false)
+                .global(true)
+                .takes_value(true)
+                .possible_values(&["auto", "s", "ms", "us", "ns"])
+                .help("Unit of numeric timestamps; auto guesses from their size [default: auto]"),
+        )
+        .arg(
+            Arg::with_name("time_zone")
+                .long("tz")
+                .required(false)
+                .global(true)
+                .takes_value(true)
+                .help("Time zone to show timestamps in: local, UTC or a name like America/New_York"),
+        )
+        .arg(
+            Arg::with_name("time_format")
+                .long("time-format")
+                .required(false)
+                .global(true)
+                .takes_value(true)
+                .help("strftime-style format for timestamps, e.g. '%H:%M:%S%.3f'"),
+        )
+        .arg(
+            Arg::with_name("files")
+                .multiple(true)
+                .help("Files or glob patterns to read, or - for stdin (default: stdin)"),
+        )
+        .subcommand(
+            SubCommand::with_name("listen")
+                .about("Format newline-delimited JSON or syslog lines sent to local sockets")
+                .arg(
+                    Arg::with_name("udp")
+                        .long("udp")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .takes_value(true)
+                        .value_name("addr")
+                        .help("Receive datagrams on an address, e.g. 127.0.0.1:5514"),
+                )
+                .arg(
+                    Arg::with_name("tcp")
+                        .long("tcp")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .takes_value(true)
+                        .value_name("addr")
+                        .help("Accept connections on an address, e.g. 127.0.0.1:5514"),
+                )
+                .arg(
+                    Arg::with_name("unix")
+                        .long("unix")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .takes_value(true)
+                        .value_name("path")
+                        .help("Accept connections on a Unix domain socket, e.g. /tmp/structy.sock"),
+                )
+                .group(
+                    ArgGroup::with_name("endpoints")
+                        .args(&["udp", "tcp", "unix"])
+                        .multiple(true)
+                        .required(true),
+                ),
+        )
         .get_matches();
 
     let no_colors = matches.is_present("no_colors");
@@ -66,32 +314,296 @@ fn main() {
         Err(_) => vec![],
     };
 
-    let fmt = lib::Formatter::new_with_params(
+    let level_props = values_t!(matches.values_of("level_properties"), String).unwrap_or_default();
+    let message_props =
+        values_t!(matches.values_of("message_properties"), String).unwrap_or_default();
+
+    let with_filename = matches.is_present("with_filename");
+    let follow = matches.is_present("follow");
+    let paths = values_t!(matches.values_of("files"), String).unwrap_or_default();
+
+    let mut fmt = Formatter::new_with_params(
         no_colors,
         no_level,
         parse_depth,
         timestamp_prop.to_string(),
         highlight_properties,
     );
+    if matches.is_present("min_level") {
+        fmt.min_level = Some(value_t_or_exit!(matches, "min_level", Level));
+    }
+    match matches.value_of("preset") {
+        None => {}
+        Some("auto") => fmt.detect_preset = true,
+        Some(name) => fmt.preset = preset::find(name),
+    }
+    if matches.is_present("output") {
+        fmt.output = value_t_or_exit!(matches, "output", Output);
+    }
+    if matches.is_present("key_order") {
+        fmt.key_order = value_t_or_exit!(matches, "key_order", KeyOrder);
+    }
+    if matches.is_present("unpack_json") {
+        fmt.unpack_json = value_t_or_exit!(matches, "unpack_json", u32);
+    }
+    fmt.parse_prefix = matches.is_present("parse_prefix");
+    fmt.flatten = matches.is_present("flatten");
+    fmt.only = field_set(&matches, "only");
+    fmt.hide = field_set(&matches, "hide");
+    fmt.level_props = level_props;
+    fmt.message_props = message_props;
+    fmt.keep_unknown_level = matches.value_of("unknown_level") != Some("drop");
+    match matches.value_of("level_scale") {
+        None | Some("auto") => {}
+        Some(_) => fmt.level_scale = Some(value_t_or_exit!(matches, "level_scale", LevelScale)),
+    }
+    if let Some(expr) = matches.value_of("filter") {
+        match Filter::parse(expr) {
+            Ok(filter) => fmt.filter = Some(filter),
+            Err(err) => {
+                eprintln!("structy: invalid --filter at {}", err.render(expr));
+                process::exit(1)
+            }
+        }
+    }
 
-    let mut line = String::new();
-    loop {
-        match io::stdin().read_line(&mut line) {
-            Ok(n) => {
-                if n == 0 {
-                    process::exit(0);
+    let now = Utc::now();
+    fmt.since = parse_time_bound(&matches, "since", now);
+    fmt.until = parse_time_bound(&matches, "until", now);
+    match matches.value_of("epoch_unit") {
+        None | Some("auto") => {}
+        Some(_) => fmt.epoch_unit = Some(value_t_or_exit!(matches, "epoch_unit", EpochUnit)),
+    }
+    if let Some(zone) = matches.value_of("time_zone") {
+        match zone.parse::<Zone>() {
+            Ok(zone) => fmt.time_zone = Some(zone),
+            Err(err) => {
+                eprintln!("structy: --tz: {}", err);
+                process::exit(1)
+            }
+        }
+    }
+    if let Some(format) = matches.value_of("time_format") {
+        if let Err(err) = timestamp::validate_format(format) {
+            eprintln!("structy: --time-format: {}", err);
+            process::exit(1)
+        }
+        fmt.time_format = Some(format.to_string());
+    }
+
+    if let Some(matches) = matches.subcommand_matches("listen") {
+        listen(matches, &fmt);
+    }
+
+    let sources = match input::expand_paths(&paths) {
+        Ok(sources) => sources,
+        Err(err) => {
+            eprintln!("structy: {}", err);
+            process::exit(1)
+        }
+    };
+
+    let prefix_for = |name: &str| {
+        if with_filename {
+            fmt.format_filename(name)
+        } else {
+            String::new()
+        }
+    };
+
+    // Stdin is already a stream, so following only applies to files
+    if follow && sources.iter().any(|source| *source != Source::Stdin) {
+        let patterns = input::patterns(&paths);
+        let mut paths = vec![];
+        let mut failed = false;
+        for source in sources {
+            match source {
+                Source::File(path) => {
+                    if !path.exists() {
+                        eprintln!("structy: {}: waiting for file to appear", path.display());
+                    }
+                    match Compression::of_file(&path) {
+                        Ok(Compression::None) | Err(_) => paths.push(path),
+                        Ok(_) => {
+                            // Compressed archives don't grow, so read them once
+                            let source = Source::File(path);
+                            let prefix = prefix_for(&source.to_string());
+                            let result = source
+                                .open()
+                                .and_then(|reader| reformat_lines(reader, &fmt, &prefix, true));
+                            if let Err(err) = result {
+                                eprintln!("structy: {}: {}", source, err);
+                                failed = true;
+                            }
+                        }
+                    }
                 }
-                match fmt.reformat_str(&line) {
-                    Ok(l) => println!("{}", l),
-                    Err(_) => print!("{}", line),
+                Source::Stdin => {
+                    eprintln!("structy: --follow can't be combined with reading stdin");
+                    process::exit(1)
                 }
-
-                line.clear();
             }
-            Err(error) => {
-                println!("stdin error: {}", error);
-                process::exit(1)
+        }
+        // Nothing left that could grow
+        if paths.is_empty() && patterns.is_empty() {
+            process::exit(if failed { 1 } else { 0 });
+        }
+        let mut follower = Follower::new(paths);
+        follower.watch(patterns);
+        let mut reassemblers: HashMap<PathBuf, Reassembler> = HashMap::new();
+        follower.run(|path, event| match event {
+            Event::Line(line) => {
+                let reassembler = reassemblers.entry(path.to_path_buf()).or_default();
+                if let Some(line) = reassembler.push(line) {
+                    print_line(&fmt, &prefix_for(&path.display().to_string()), &line);
+                }
+            }
+            Event::Reset => {
+                // A partial line cut off by truncation or rotation won't be finished
+                if let Some(reassembler) = reassemblers.get_mut(path) {
+                    while let Some(line) = reassembler.finish() {
+                        print_line(&fmt, &prefix_for(&path.display().to_string()), &line);
+                    }
+                }
             }
+            Event::Error(err) => eprintln!("structy: {}: {}", path.display(), err),
+        });
+    }
+
+    let mut failed = false;
+    for source in &sources {
+        let prefix = prefix_for(&source.to_string());
+        let stop_after_until = *source != Source::Stdin;
+        let result = source
+            .open()
+            .and_then(|reader| reformat_lines(reader, &fmt, &prefix, stop_after_until));
+        if let Err(err) = result {
+            eprintln!("structy: {}: {}", source, err);
+            failed = true;
         }
     }
+    process::exit(if failed { 1 } else { 0 });
+}
+
+fn parse_time_bound(matches: &ArgMatches, name: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let value = matches.value_of(name)?;
+    match timestamp::parse_bound(value, now) {
+        Ok(bound) => Some(bound),
+        Err(err) => {
+            eprintln!("structy: --{}: {}", name, err);
+            process::exit(1)
+        }
+    }
+}
+
+fn field_set(matches: &ArgMatches, name: &str) -> Option<FieldSet> {
+    let paths = values_t!(matches.values_of(name), String).ok()?;
+    match FieldSet::new(&paths) {
+        Ok(set) => Some(set),
+        Err(err) => {
+            eprintln!("structy: --{}: {}", name, err);
+            process::exit(1)
+        }
+    }
+}
+
+/// Formats lines sent to the `listen` subcommand's sockets until it's
+/// interrupted or terminated.
+fn listen(matches: &ArgMatches, fmt: &Formatter) -> ! {
+    let mut endpoints = vec![];
+    if matches.is_present("udp") {
+        let addrs = values_t_or_exit!(matches, "udp", SocketAddr);
+        endpoints.extend(addrs.into_iter().map(Endpoint::Udp));
+    }
+    if matches.is_present("tcp") {
+        let addrs = values_t_or_exit!(matches, "tcp", SocketAddr);
+        endpoints.extend(addrs.into_iter().map(Endpoint::Tcp));
+    }
+    if let Some(paths) = matches.values_of("unix") {
+        endpoints.extend(paths.map(|path| Endpoint::Unix(PathBuf::from(path))));
+    }
+    let listener = match Listener::bind(&endpoints) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("structy: listen: {}", err);
+            process::exit(1)
+        }
+    };
+    for endpoint in listener.endpoints() {
+        eprintln!("structy: listening on {}", endpoint);
+    }
+    close_on_signal(listener.closer());
+    listener.run(|event| match event {
+        listen::Event::Line(line) => {
+            print_line(fmt, "", line);
+        }
+        listen::Event::Error(endpoint, err) => eprintln!("structy: {}: {}", endpoint, err),
+    });
+    // Dropping the listener removes its Unix socket files
+    drop(listener);
+    process::exit(0)
+}
+
+/// Stops the listener on Ctrl-C or `kill`, so it's shut down cleanly.
+#[cfg(unix)]
+fn close_on_signal(closer: Closer) {
+    use signal_hook::consts::{SIGINT, SIGTERM};
+    use signal_hook::iterator::Signals;
+
+    let mut signals = match Signals::new([SIGINT, SIGTERM]) {
+        Ok(signals) => signals,
+        Err(err) => {
+            eprintln!("structy: listen: {}", err);
+            return;
+        }
+    };
+    thread::spawn(move || {
+        if signals.forever().next().is_some() {
+            closer.close();
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn close_on_signal(_closer: Closer) {}
+
+/// Reformats every line from `reader`. With `stop_after_until`, reading
+/// stops at the first record later than `--until`, assuming the input is
+/// sorted by time.
+fn reformat_lines<R: BufRead>(
+    mut reader: R,
+    fmt: &Formatter,
+    prefix: &str,
+    stop_after_until: bool,
+) -> io::Result<()> {
+    let mut line = String::new();
+    let mut reassembler = Reassembler::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            while let Some(line) = reassembler.finish() {
+                if !print_line(fmt, prefix, &line) && stop_after_until {
+                    break;
+                }
+            }
+            return Ok(());
+        }
+        let line = match reassembler.push(&line) {
+            Some(line) => line,
+            None => continue,
+        };
+        if !print_line(fmt, prefix, &line) && stop_after_until {
+            return Ok(());
+        }
+    }
+}
+
+/// Prints a reformatted line, returning false if it was later than `--until`.
+fn print_line(fmt: &Formatter, prefix: &str, line: &str) -> bool {
+    match fmt.reformat_line(line) {
+        Line::Show(l) => println!("{}{}", prefix, l),
+        Line::Skip => {}
+        Line::AfterUntil => return false,
+    }
+    true
 }
@@ -0,0 +1,120 @@
+//! Finding JSON records after a text prefix, such as docker-compose's
+//! `web_1  | {...}` or syslog's `Jan 29 00:50:43 host app[123]: {...}`.
+
+use chrono::{DateTime, Local};
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::sync::OnceLock;
+use syslog;
+
+/// How many `{` are tried as the start of the object, so a long line full of
+/// braces doesn't take quadratic time.
+const MAX_ATTEMPTS: usize = 16;
+
+/// Splits a line into the text before the first `{` that begins a JSON
+/// object running to the end of the line, and that object.
+pub fn split(line: &str) -> Option<(&str, Map<String, Value>)> {
+    let line = line.trim_end();
+    if !line.ends_with('}') {
+        return None;
+    }
+    for (i, _) in line.match_indices('{').take(MAX_ATTEMPTS) {
+        if let Ok(Value::Object(obj)) = ::serde_json::from_str(&line[i..]) {
+            return Some((&line[..i], obj));
+        }
+    }
+    None
+}
+
+fn compose_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"^([\w.-]+)\s+\|\s*$").unwrap())
+}
+
+/// Reads fields out of a known prefix format: docker-compose's service
+/// name, or a syslog time, host, app and pid, read as `syslog::parse` reads
+/// an RFC 3164 header.
+pub fn parse(prefix: &str, now: DateTime<Local>) -> Option<Map<String, Value>> {
+    let mut fields = Map::new();
+    if let Some(caps) = compose_re().captures(prefix) {
+        fields.insert("service".to_string(), json!(&caps[1]));
+        return Some(fields);
+    }
+    let message = syslog::parse_3164(prefix.trim_end(), now, &mut fields)?;
+    if !message.is_empty() || !fields.contains_key("app") {
+        return None;
+    }
+    Some(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, split};
+    use chrono::{Local, TimeZone};
+    use serde_json::Value;
+    use timestamp;
+
+    #[test]
+    fn split_finds_object() {
+        let (prefix, obj) = split("web_1  | {\"level\": \"info\"}\n").unwrap();
+        assert_eq!(prefix, "web_1  | ");
+        assert_eq!(Value::Object(obj), json!({"level": "info"}));
+    }
+
+    #[test]
+    fn split_skips_braces_that_arent_json() {
+        let (prefix, obj) = split("[{worker}] {\"a\": {\"b\": 1}}").unwrap();
+        assert_eq!(prefix, "[{worker}] ");
+        assert_eq!(Value::Object(obj), json!({"a": {"b": 1}}));
+    }
+
+    #[test]
+    fn split_needs_object_to_end_of_line() {
+        assert!(split("got {\"a\": 1} back").is_none());
+        assert!(split("no json here").is_none());
+        assert!(split("array [1, 2]").is_none());
+    }
+
+    #[test]
+    fn split_gives_up_after_many_braces() {
+        let line = format!("{} {{\"a\": 1}}", "{".repeat(super::MAX_ATTEMPTS));
+        assert!(split(&line).is_none());
+        let line = format!("{} {{\"a\": 1}}", "{".repeat(super::MAX_ATTEMPTS - 1));
+        assert!(split(&line).is_some());
+    }
+
+    #[test]
+    fn parse_compose() {
+        assert_eq!(
+            Value::Object(parse("web_1  | ", Local::now()).unwrap()),
+            json!({"service": "web_1"})
+        );
+    }
+
+    #[test]
+    fn parse_syslog() {
+        let now = Local.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        let time = |month, day| {
+            let time = Local.with_ymd_and_hms(2024, month, day, 0, 50, 43).unwrap();
+            timestamp::render(&time.fixed_offset(), None, None)
+        };
+        assert_eq!(
+            Value::Object(parse("Jan 29 00:50:43 host app[123]: ", now).unwrap()),
+            json!({"time": time(1, 29), "host": "host", "app": "app", "pid": 123})
+        );
+        assert_eq!(
+            Value::Object(parse("Jan  9 00:50:43 host cron: ", now).unwrap()),
+            json!({"time": time(1, 9), "host": "host", "app": "cron"})
+        );
+        assert_eq!(
+            Value::Object(parse("Jan  9 00:50:43 cron: ", now).unwrap()),
+            json!({"time": time(1, 9), "app": "cron"})
+        );
+    }
+
+    #[test]
+    fn parse_unknown() {
+        assert!(parse("2018-01-29 INFO ", Local::now()).is_none());
+        assert!(parse("Jan 29 00:50:43 host app[123]: text ", Local::now()).is_none());
+    }
+}
@@ -0,0 +1,272 @@
+use level::{Level, LevelScale};
+use serde_json::{Map, Value};
+use timestamp::EpochUnit;
+
+/// The field conventions of a structured logging library.
+#[derive(Debug, PartialEq)]
+pub struct Preset {
+    pub name: &'static str,
+    pub timestamp_props: &'static [&'static str],
+    pub level_props: &'static [&'static str],
+    pub message_props: &'static [&'static str],
+    /// Message templates to render when there's no plain message
+    pub template_props: &'static [&'static str],
+    /// Where the log call came from, e.g. `main.go:42`
+    pub caller_props: &'static [&'static str],
+    /// Errors and stack traces
+    pub error_props: &'static [&'static str],
+    /// Bookkeeping fields that aren't worth showing
+    pub hidden_props: &'static [&'static str],
+    pub level_scale: Option<LevelScale>,
+    pub epoch_unit: Option<EpochUnit>,
+    /// The level of records that don't give one
+    pub default_level: Option<Level>,
+}
+
+pub static PRESETS: &[Preset] = &[
+    Preset {
+        name: "bunyan",
+        timestamp_props: &["time"],
+        level_props: &["level"],
+        message_props: &["msg"],
+        template_props: &[],
+        caller_props: &["src"],
+        error_props: &["err"],
+        hidden_props: &["v", "hostname", "pid"],
+        level_scale: Some(LevelScale::Bunyan),
+        epoch_unit: None,
+        default_level: None,
+    },
+    Preset {
+        name: "pino",
+        timestamp_props: &["time"],
+        level_props: &["level"],
+        message_props: &["msg"],
+        template_props: &[],
+        caller_props: &["caller"],
+        error_props: &["err"],
+        hidden_props: &["v", "hostname", "pid"],
+        level_scale: Some(LevelScale::Bunyan),
+        epoch_unit: Some(EpochUnit::Millis),
+        default_level: None,
+    },
+    Preset {
+        name: "zap",
+        timestamp_props: &["ts"],
+        level_props: &["level"],
+        message_props: &["msg"],
+        template_props: &[],
+        caller_props: &["caller"],
+        error_props: &["error", "errorVerbose", "stacktrace"],
+        hidden_props: &[],
+        level_scale: None,
+        epoch_unit: Some(EpochUnit::Seconds),
+        default_level: None,
+    },
+    Preset {
+        name: "zerolog",
+        timestamp_props: &["time"],
+        level_props: &["level"],
+        message_props: &["message"],
+        template_props: &[],
+        caller_props: &["caller"],
+        error_props: &["error", "stack"],
+        hidden_props: &[],
+        level_scale: None,
+        epoch_unit: None,
+        default_level: None,
+    },
+    Preset {
+        name: "logrus",
+        timestamp_props: &["time"],
+        level_props: &["level"],
+        message_props: &["msg"],
+        template_props: &[],
+        caller_props: &["file", "func"],
+        error_props: &["error"],
+        hidden_props: &[],
+        level_scale: None,
+        epoch_unit: None,
+        default_level: None,
+    },
+    Preset {
+        name: "slog",
+        timestamp_props: &["time"],
+        level_props: &["level"],
+        message_props: &["msg"],
+        template_props: &[],
+        caller_props: &["source"],
+        error_props: &["err", "error"],
+        hidden_props: &[],
+        level_scale: None,
+        epoch_unit: None,
+        default_level: None,
+    },
+    Preset {
+        name: "structlog",
+        timestamp_props: &["timestamp"],
+        level_props: &["level"],
+        message_props: &["event"],
+        template_props: &[],
+        caller_props: &["func_name"],
+        error_props: &["exception", "exc_info"],
+        hidden_props: &[],
+        level_scale: None,
+        epoch_unit: None,
+        default_level: None,
+    },
+    Preset {
+        // Serilog's compact log event format
+        name: "clef",
+        timestamp_props: &["@t"],
+        level_props: &["@l"],
+        message_props: &["@m"],
+        template_props: &["@mt"],
+        caller_props: &[],
+        error_props: &["@x"],
+        hidden_props: &["@i", "@r"],
+        level_scale: None,
+        epoch_unit: None,
+        default_level: Some(Level::Info),
+    },
+];
+
+/// Looks up a preset by name.
+pub fn find(name: &str) -> Option<&'static Preset> {
+    PRESETS.iter().find(|p| p.name == name)
+}
+
+pub fn names() -> Vec<&'static str> {
+    PRESETS.iter().map(|p| p.name).collect()
+}
+
+fn is_string(obj: &Map<String, Value>, key: &str) -> bool {
+    obj.get(key).is_some_and(|v| v.is_string())
+}
+
+fn is_number(obj: &Map<String, Value>, key: &str) -> bool {
+    obj.get(key).is_some_and(|v| v.is_number())
+}
+
+/// Guesses which library produced a record from the shape of its fields.
+pub fn detect(obj: &Map<String, Value>) -> Option<&'static Preset> {
+    let name = if obj.contains_key("@t") && (obj.contains_key("@mt") || obj.contains_key("@m")) {
+        "clef"
+    } else if is_number(obj, "level") && is_number(obj, "v") && obj.contains_key("name") {
+        "bunyan"
+    } else if is_number(obj, "level") && is_number(obj, "time") {
+        "pino"
+    } else if is_number(obj, "ts") && is_string(obj, "level") {
+        "zap"
+    } else if is_string(obj, "event")
+        && (obj.contains_key("level") || obj.contains_key("timestamp"))
+    {
+        "structlog"
+    } else if obj.get("source").is_some_and(|v| v.is_object())
+        || (obj
+            .get("level")
+            .and_then(|v| v.as_str())
+            .is_some_and(|l| l.len() > 1 && l == l.to_uppercase())
+            && is_string(obj, "msg"))
+    {
+        "slog"
+    } else if is_string(obj, "level") && is_string(obj, "message") {
+        "zerolog"
+    } else if is_string(obj, "level") && is_string(obj, "msg") {
+        "logrus"
+    } else {
+        return None;
+    };
+    find(name)
+}
+
+/// Renders a caller field: strings as-is, and `{file, line}` style objects
+/// (bunyan's `src`, slog's `source`) as `file:line`.
+pub fn format_caller(val: &Value) -> Option<String> {
+    match *val {
+        Value::String(ref s) => Some(s.to_string()),
+        Value::Object(ref obj) => {
+            let file = obj.get("file").and_then(|v| v.as_str())?;
+            match obj.get("line") {
+                Some(line) => Some(format!("{}:{}", file, line)),
+                None => Some(file.to_string()),
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{detect, find, format_caller};
+    use serde_json::Value;
+
+    fn detected(json: &str) -> Option<&'static str> {
+        match ::serde_json::from_str(json).unwrap() {
+            Value::Object(obj) => detect(&obj).map(|p| p.name),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn preset_find() {
+        assert_eq!(find("zap").unwrap().timestamp_props, &["ts"]);
+        assert!(find("log4j").is_none());
+    }
+
+    #[test]
+    fn preset_detect() {
+        assert_eq!(
+            detected("{\"name\": \"app\", \"hostname\": \"h\", \"pid\": 1, \"level\": 30, \"msg\": \"hi\", \"time\": \"2018-01-29T00:50:43.176Z\", \"v\": 0}"),
+            Some("bunyan")
+        );
+        assert_eq!(
+            detected("{\"level\": 30, \"time\": 1706489443176, \"pid\": 1, \"hostname\": \"h\", \"msg\": \"hi\"}"),
+            Some("pino")
+        );
+        assert_eq!(
+            detected("{\"level\": \"info\", \"ts\": 1706489443.176, \"caller\": \"main.go:42\", \"msg\": \"hi\"}"),
+            Some("zap")
+        );
+        assert_eq!(
+            detected(
+                "{\"event\": \"hi\", \"level\": \"info\", \"timestamp\": \"2018-01-29T00:50:43Z\"}"
+            ),
+            Some("structlog")
+        );
+        assert_eq!(
+            detected("{\"time\": \"2018-01-29T00:50:43Z\", \"level\": \"INFO\", \"msg\": \"hi\"}"),
+            Some("slog")
+        );
+        assert_eq!(
+            detected(
+                "{\"level\": \"info\", \"time\": \"2018-01-29T00:50:43Z\", \"message\": \"hi\"}"
+            ),
+            Some("zerolog")
+        );
+        assert_eq!(
+            detected("{\"level\": \"info\", \"time\": \"2018-01-29T00:50:43Z\", \"msg\": \"hi\"}"),
+            Some("logrus")
+        );
+        assert_eq!(
+            detected(
+                "{\"@t\": \"2018-01-29T00:50:43Z\", \"@mt\": \"Hello {Name}\", \"Name\": \"bob\"}"
+            ),
+            Some("clef")
+        );
+        assert_eq!(detected("{\"a\": 1}"), None);
+    }
+
+    #[test]
+    fn caller_formats() {
+        assert_eq!(
+            format_caller(&json!("main.go:42")),
+            Some("main.go:42".to_string())
+        );
+        assert_eq!(
+            format_caller(&json!({"function": "main.run", "file": "main.go", "line": 42})),
+            Some("main.go:42".to_string())
+        );
+        assert_eq!(format_caller(&json!(42)), None);
+    }
+}
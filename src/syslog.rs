@@ -0,0 +1,378 @@
+//! Syslog lines in the RFC 5424 format,
+//! `<165>1 2003-10-11T22:14:15.003Z host app 123 ID47 [id k="v"] msg`,
+//! and the older RFC 3164 format, `<34>Oct 11 22:14:15 host su[123]: msg`.
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use level::{Level, LevelScale};
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::sync::OnceLock;
+use timestamp;
+
+const FACILITIES: &[&str] = &[
+    "kern",
+    "user",
+    "mail",
+    "daemon",
+    "auth",
+    "syslog",
+    "lpr",
+    "news",
+    "uucp",
+    "cron",
+    "authpriv",
+    "ftp",
+    "ntp",
+    "security",
+    "console",
+    "solaris-cron",
+    "local0",
+    "local1",
+    "local2",
+    "local3",
+    "local4",
+    "local5",
+    "local6",
+    "local7",
+];
+
+/// Splits a syslog line into its message, `null` if there's none, and the
+/// header's fields: `time`, `level`, `facility`, `host`, `app`, `pid`,
+/// `msgid` and an object per structured data element. RFC 3164 times have
+/// no year, so they're taken to be local times within the year before `now`.
+pub fn parse(line: &str, now: DateTime<Local>) -> Option<(Value, Map<String, Value>)> {
+    let line = line.trim_end_matches(&['\n', '\r'][..]);
+    let rest = line.strip_prefix('<')?;
+    let end = rest.find('>')?;
+    let pri: usize = match &rest[..end] {
+        p if !p.is_empty() && p.len() <= 3 && p.chars().all(|c| c.is_ascii_digit()) => {
+            p.parse().ok()?
+        }
+        _ => return None,
+    };
+    let facility = FACILITIES.get(pri / 8)?;
+    let level = Level::from_number((pri % 8) as f64, Some(LevelScale::Syslog))?;
+
+    let mut fields = Map::new();
+    let rest = &rest[end + 1..];
+    let message = match rest.strip_prefix("1 ") {
+        Some(rest) => parse_5424(rest, &mut fields)?,
+        None => parse_3164(rest, now, &mut fields)?,
+    };
+    // The header's fields come first
+    let mut header = Map::new();
+    if let Some(time) = fields.shift_remove("time") {
+        header.insert("time".to_string(), time);
+    }
+    header.insert("level".to_string(), json!(level.name()));
+    header.insert("facility".to_string(), json!(facility));
+    header.extend(fields);
+
+    Some((message_value(message), header))
+}
+
+/// Splits an RFC 3164 line as written to files such as `/var/log/syslog`,
+/// without the priority, so there's no `level` or `facility`.
+pub fn parse_file_line(line: &str, now: DateTime<Local>) -> Option<(Value, Map<String, Value>)> {
+    let line = line.trim_end_matches(&['\n', '\r'][..]);
+    let mut fields = Map::new();
+    let message = parse_3164(line, now, &mut fields)?;
+    Some((message_value(message), fields))
+}
+
+fn message_value(message: &str) -> Value {
+    let message = message.trim_start_matches('\u{feff}');
+    if message.is_empty() {
+        Value::Null
+    } else {
+        json!(message)
+    }
+}
+
+fn insert_unless_nil(fields: &mut Map<String, Value>, key: &str, val: &str) {
+    if val == "-" {
+        return;
+    }
+    match val.parse::<u64>() {
+        Ok(n) if key == "pid" => fields.insert(key.to_string(), json!(n)),
+        _ => fields.insert(key.to_string(), json!(val)),
+    };
+}
+
+fn parse_5424<'a>(line: &'a str, fields: &mut Map<String, Value>) -> Option<&'a str> {
+    let mut parts = line.splitn(6, ' ');
+    let time = parts.next()?;
+    let host = parts.next()?;
+    let app = parts.next()?;
+    let pid = parts.next()?;
+    let msgid = parts.next()?;
+    let rest = parts.next().unwrap_or("");
+
+    if time != "-" {
+        let time = DateTime::parse_from_rfc3339(time).ok()?;
+        fields.insert(
+            "time".to_string(),
+            json!(timestamp::render(&time, None, None)),
+        );
+    }
+    insert_unless_nil(fields, "host", host);
+    insert_unless_nil(fields, "app", app);
+    insert_unless_nil(fields, "pid", pid);
+    insert_unless_nil(fields, "msgid", msgid);
+
+    let rest = match rest.strip_prefix('-') {
+        Some(rest) => rest,
+        None => parse_structured_data(rest, fields)?,
+    };
+    match rest.strip_prefix(' ') {
+        Some(message) => Some(message),
+        None if rest.is_empty() => Some(rest),
+        None => None,
+    }
+}
+
+/// Parses `[id name="value" ...]` elements into an object per id, returning
+/// the text after them.
+fn parse_structured_data<'a>(
+    mut rest: &'a str,
+    fields: &mut Map<String, Value>,
+) -> Option<&'a str> {
+    if !rest.starts_with('[') {
+        return None;
+    }
+    while let Some(element) = rest.strip_prefix('[') {
+        let id_end = element.find([' ', ']'])?;
+        let mut params = Map::new();
+        let mut s = &element[id_end..];
+        loop {
+            s = s.trim_start_matches(' ');
+            if let Some(after) = s.strip_prefix(']') {
+                rest = after;
+                break;
+            }
+            let eq = s.find("=\"")?;
+            let (value, after) = param_value(&s[eq + 2..])?;
+            params.insert(s[..eq].to_string(), json!(value));
+            s = after;
+        }
+        fields.insert(element[..id_end].to_string(), Value::Object(params));
+    }
+    Some(rest)
+}
+
+/// Reads a quoted parameter value up to its closing quote. Only `"`, `\`
+/// and `]` are escaped; other backslashes are kept.
+fn param_value(s: &str) -> Option<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &s[i + 1..])),
+            '\\' => match chars.next()?.1 {
+                c @ '"' | c @ '\\' | c @ ']' => value.push(c),
+                c => {
+                    value.push('\\');
+                    value.push(c);
+                }
+            },
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+fn rfc3164_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"^([A-Z][a-z]{2}) ([ \d]\d) (\d{2}:\d{2}:\d{2}) (?:(\S*[^\s:]) )?(?:([^\s\[:]+)(?:\[([^\]]*)\])?:)? ?(.*)$",
+        )
+        .unwrap()
+    })
+}
+
+/// Reads an RFC 3164 header, without the priority, into `time`, `host`,
+/// `app` and `pid` fields, returning the message after it. The hostname may
+/// be missing, as it is from some senders.
+pub(crate) fn parse_3164<'a>(
+    line: &'a str,
+    now: DateTime<Local>,
+    fields: &mut Map<String, Value>,
+) -> Option<&'a str> {
+    let caps = rfc3164_re().captures(line)?;
+    let month = match &caps[1] {
+        "Jan" => 1,
+        "Feb" => 2,
+        "Mar" => 3,
+        "Apr" => 4,
+        "May" => 5,
+        "Jun" => 6,
+        "Jul" => 7,
+        "Aug" => 8,
+        "Sep" => 9,
+        "Oct" => 10,
+        "Nov" => 11,
+        "Dec" => 12,
+        _ => return None,
+    };
+    let day = caps[2].trim().parse().ok()?;
+    let time = NaiveTime::parse_from_str(&caps[3], "%H:%M:%S").ok()?;
+    let time = infer_year(month, day, time, now.naive_local())
+        .and_then(|t| Local.from_local_datetime(&t).earliest());
+    if let Some(time) = time {
+        fields.insert(
+            "time".to_string(),
+            json!(timestamp::render(&time.fixed_offset(), None, None)),
+        );
+    }
+    if let Some(host) = caps.get(4) {
+        fields.insert("host".to_string(), json!(host.as_str()));
+    }
+    if let Some(app) = caps.get(5) {
+        fields.insert("app".to_string(), json!(app.as_str()));
+    }
+    if let Some(pid) = caps.get(6) {
+        insert_unless_nil(fields, "pid", pid.as_str());
+    }
+    Some(caps.get(7).map_or("", |m| m.as_str()))
+}
+
+/// Picks the year for a date without one: this year, unless that would be
+/// more than a day in the future.
+fn infer_year(month: u32, day: u32, time: NaiveTime, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let this_year = NaiveDate::from_ymd_opt(now.year(), month, day).map(|d| d.and_time(time));
+    match this_year {
+        Some(t) if t <= now + Duration::days(1) => Some(t),
+        _ => NaiveDate::from_ymd_opt(now.year() - 1, month, day).map(|d| d.and_time(time)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{infer_year, parse, parse_file_line};
+    use chrono::{Local, NaiveDate, NaiveTime, TimeZone};
+    use serde_json::Value;
+
+    #[test]
+    fn parse_rfc5424() {
+        let (message, fields) = parse(
+            "<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog 123 ID47 [exampleSDID@32473 iut=\"3\" eventSource=\"App \\\"x\\\"\"][meta seq=\"1\"] \u{feff}An application event\n",
+            Local::now(),
+        )
+        .unwrap();
+        assert_eq!(message, json!("An application event"));
+        assert_eq!(
+            Value::Object(fields),
+            json!({
+                "time": "2003-10-11T22:14:15.003Z",
+                "level": "info",
+                "facility": "local4",
+                "host": "mymachine.example.com",
+                "app": "evntslog",
+                "pid": 123,
+                "msgid": "ID47",
+                "exampleSDID@32473": {"iut": "3", "eventSource": "App \"x\""},
+                "meta": {"seq": "1"}
+            })
+        );
+    }
+
+    #[test]
+    fn parse_rfc5424_nil_values() {
+        let (message, fields) = parse("<11>1 - host - - - -", Local::now()).unwrap();
+        assert_eq!(message, Value::Null);
+        assert_eq!(
+            Value::Object(fields),
+            json!({"level": "error", "facility": "user", "host": "host"})
+        );
+        let (message, _) = parse("<11>1 - - - - - - {\"a\": 1}", Local::now()).unwrap();
+        assert_eq!(message, json!("{\"a\": 1}"));
+    }
+
+    #[test]
+    fn parse_rfc3164() {
+        let now = Local.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        let (message, fields) = parse(
+            "<34>Oct 11 22:14:15 mymachine su[123]: 'su root' failed",
+            now,
+        )
+        .unwrap();
+        assert_eq!(message, json!("'su root' failed"));
+        let time = Local.with_ymd_and_hms(2023, 10, 11, 22, 14, 15).unwrap();
+        assert_eq!(
+            Value::Object(fields),
+            json!({
+                "time": super::timestamp::render(&time.fixed_offset(), None, None),
+                "level": "fatal",
+                "facility": "auth",
+                "host": "mymachine",
+                "app": "su",
+                "pid": 123
+            })
+        );
+    }
+
+    #[test]
+    fn parse_rfc3164_without_tag() {
+        let (message, fields) = parse("<13>Jan  2 03:04:05 host just text", Local::now()).unwrap();
+        assert_eq!(message, json!("just text"));
+        assert_eq!(fields["host"], json!("host"));
+        assert!(fields.get("app").is_none());
+    }
+
+    #[test]
+    fn parse_rfc3164_without_host() {
+        let (message, fields) = parse("<13>Oct 11 22:14:15 su: msg", Local::now()).unwrap();
+        assert_eq!(message, json!("msg"));
+        assert_eq!(fields["app"], json!("su"));
+        assert!(fields.get("host").is_none());
+        let (_, fields) = parse("<13>Oct 11 22:14:15 su[7]: msg", Local::now()).unwrap();
+        assert_eq!(fields["pid"], json!(7));
+        assert!(fields.get("host").is_none());
+    }
+
+    #[test]
+    fn parse_file_lines() {
+        let now = Local.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        let (message, fields) =
+            parse_file_line("Oct 11 22:14:15 host app[1]: text\n", now).unwrap();
+        assert_eq!(message, json!("text"));
+        let time = Local.with_ymd_and_hms(2023, 10, 11, 22, 14, 15).unwrap();
+        assert_eq!(
+            Value::Object(fields),
+            json!({
+                "time": super::timestamp::render(&time.fixed_offset(), None, None),
+                "host": "host",
+                "app": "app",
+                "pid": 1
+            })
+        );
+        assert!(parse_file_line("<13>Oct 11 22:14:15 host app: text", now).is_none());
+        assert!(parse_file_line("2024-01-29 12:00:00 text", now).is_none());
+        assert!(parse("Oct 11 22:14:15 host app: text", now).is_none());
+    }
+
+    #[test]
+    fn parse_rejects_other_lines() {
+        assert!(parse("plain text", Local::now()).is_none());
+        assert!(parse("<999>1 - - - - - -", Local::now()).is_none());
+        assert!(parse("<abc>Oct 11 22:14:15 host x", Local::now()).is_none());
+        assert!(parse("<13>1 yesterday host app - - -", Local::now()).is_none());
+        assert!(parse("<13>1 - host app - - [broken", Local::now()).is_none());
+    }
+
+    #[test]
+    fn infer_year_wraps() {
+        let t = NaiveTime::from_hms_opt(0, 0, 0).unwrap();
+        let jan = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_time(t);
+        assert_eq!(
+            infer_year(12, 31, t, jan),
+            NaiveDate::from_ymd_opt(2023, 12, 31).map(|d| d.and_time(t))
+        );
+        assert_eq!(
+            infer_year(1, 2, t, jan),
+            NaiveDate::from_ymd_opt(2024, 1, 2).map(|d| d.and_time(t))
+        );
+        assert_eq!(infer_year(2, 30, t, jan), None);
+    }
+}
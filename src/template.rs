@@ -0,0 +1,126 @@
+//! Rendering Serilog-style message templates, e.g. `User {UserId} logged in`.
+//! See https://messagetemplates.org for the syntax.
+
+use serde_json::{Map, Value};
+
+/// A rendered template and the properties substituted into it.
+#[derive(Debug, PartialEq)]
+pub struct Rendered {
+    pub text: String,
+    pub used: Vec<String>,
+}
+
+/// Substitutes `props` into `template`. Holes with a format specifier take
+/// their text from `renderings` (CLEF's `@r`) when it's available, since
+/// .NET format strings can't be applied here. Holes naming a missing
+/// property are left as they are.
+pub fn render(
+    template: &str,
+    props: &Map<String, Value>,
+    renderings: Option<&Vec<Value>>,
+) -> Rendered {
+    let mut text = String::new();
+    let mut used = vec![];
+    let mut formatted_holes = 0;
+    let mut rest = template;
+
+    while let Some(i) = rest.find(['{', '}']) {
+        text.push_str(&rest[..i]);
+        let tail = &rest[i..];
+        if tail.starts_with("{{") || tail.starts_with("}}") {
+            text.push_str(&tail[..1]);
+            rest = &tail[2..];
+            continue;
+        }
+        let end = match tail.find('}') {
+            Some(end) if tail.starts_with('{') => end,
+            _ => {
+                text.push_str(&tail[..1]);
+                rest = &tail[1..];
+                continue;
+            }
+        };
+        let hole = &tail[..=end];
+        rest = &tail[end + 1..];
+
+        let body = hole[1..hole.len() - 1].trim_start_matches(['@', '$']);
+        let (name, has_format) = match body.find([',', ':']) {
+            Some(j) => (&body[..j], body[j..].contains(':')),
+            None => (body, false),
+        };
+        let rendering = if has_format {
+            formatted_holes += 1;
+            renderings
+                .and_then(|r| r.get(formatted_holes - 1))
+                .and_then(|v| v.as_str())
+        } else {
+            None
+        };
+        let valid = !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+        match props.get(name) {
+            Some(val) if valid => {
+                match (rendering, val) {
+                    (Some(r), _) => text.push_str(r),
+                    (None, Value::String(s)) => text.push_str(s),
+                    (None, val) => text.push_str(&val.to_string()),
+                }
+                if !used.iter().any(|u| u == name) {
+                    used.push(name.to_string());
+                }
+            }
+            _ => text.push_str(hole),
+        }
+    }
+    text.push_str(rest);
+
+    Rendered { text, used }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render;
+    use serde_json::{Map, Value};
+
+    fn props(json: &str) -> Map<String, Value> {
+        match ::serde_json::from_str(json).unwrap() {
+            Value::Object(obj) => obj,
+            _ => panic!("not an object"),
+        }
+    }
+
+    #[test]
+    fn render_substitutes_props() {
+        let p = props("{\"UserId\": 42, \"Name\": \"bob\", \"Tags\": [\"a\"]}");
+        let r = render("User {UserId} ({@Name}) tagged {$Tags}", &p, None);
+        assert_eq!(r.text, "User 42 (bob) tagged [\"a\"]");
+        assert_eq!(r.used, vec!["UserId", "Name", "Tags"]);
+    }
+
+    #[test]
+    fn render_leaves_missing_and_escaped() {
+        let p = props("{\"a\": 1}");
+        let r = render("{{literal}} {missing} {a} {not a hole} }", &p, None);
+        assert_eq!(r.text, "{literal} {missing} 1 {not a hole} }");
+        assert_eq!(r.used, vec!["a"]);
+    }
+
+    #[test]
+    fn render_uses_renderings_for_formats() {
+        let p = props("{\"Elapsed\": 12.3456, \"Count\": 3}");
+        let renderings = vec![json!("12.35")];
+        let r = render(
+            "Took {Elapsed:0.00} ms for {Count,3} items",
+            &p,
+            Some(&renderings),
+        );
+        assert_eq!(r.text, "Took 12.35 ms for 3 items");
+        let r = render("Took {Elapsed:0.00} ms", &p, None);
+        assert_eq!(r.text, "Took 12.3456 ms");
+    }
+
+    #[test]
+    fn render_unclosed_hole() {
+        let p = props("{\"a\": 1}");
+        assert_eq!(render("oops {a", &p, None).text, "oops {a");
+    }
+}
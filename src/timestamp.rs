@@ -0,0 +1,426 @@
+use chrono::format::{Item, StrftimeItems};
+use chrono::{
+    DateTime, Duration, FixedOffset, Local, NaiveDate, NaiveTime, SecondsFormat, TimeZone, Utc,
+    Weekday,
+};
+use chrono_tz::Tz;
+use iso8601;
+use serde_json::Number;
+use std::str::FromStr;
+
+/// A time zone to display timestamps in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Zone {
+    Local,
+    Utc,
+    Named(Tz),
+}
+
+impl FromStr for Zone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Zone, String> {
+        match s.to_lowercase().as_str() {
+            "local" => return Ok(Zone::Local),
+            "utc" | "z" => return Ok(Zone::Utc),
+            _ => {}
+        }
+        match s.parse::<Tz>() {
+            Ok(tz) => Ok(Zone::Named(tz)),
+            Err(_) => Err(format!(
+                "unknown time zone `{}`, expected local, UTC or a name like America/New_York",
+                s
+            )),
+        }
+    }
+}
+
+/// The unit of a numeric timestamp counting from the Unix epoch.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EpochUnit {
+    Seconds,
+    Millis,
+    Micros,
+    Nanos,
+}
+
+impl EpochUnit {
+    /// Guesses the unit from the magnitude of a timestamp, assuming it falls
+    /// somewhere between 1973 and 5138. Smaller values aren't treated as
+    /// timestamps at all, since they're more likely durations or counters.
+    pub fn infer(n: f64) -> Option<EpochUnit> {
+        let n = n.abs();
+        if n < 1e8 {
+            None
+        } else if n < 1e11 {
+            Some(EpochUnit::Seconds)
+        } else if n < 1e14 {
+            Some(EpochUnit::Millis)
+        } else if n < 1e17 {
+            Some(EpochUnit::Micros)
+        } else {
+            Some(EpochUnit::Nanos)
+        }
+    }
+
+    fn nanos(self) -> i64 {
+        match self {
+            EpochUnit::Seconds => 1_000_000_000,
+            EpochUnit::Millis => 1_000_000,
+            EpochUnit::Micros => 1_000,
+            EpochUnit::Nanos => 1,
+        }
+    }
+}
+
+impl FromStr for EpochUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<EpochUnit, String> {
+        match s {
+            "s" => Ok(EpochUnit::Seconds),
+            "ms" => Ok(EpochUnit::Millis),
+            "us" | "µs" => Ok(EpochUnit::Micros),
+            "ns" => Ok(EpochUnit::Nanos),
+            _ => Err(format!(
+                "unknown epoch unit `{}`, expected s, ms, us or ns",
+                s
+            )),
+        }
+    }
+}
+
+/// Converts a numeric timestamp counting from the Unix epoch. When `unit` is
+/// `None` it's inferred from the magnitude.
+pub fn from_epoch(n: &Number, unit: Option<EpochUnit>) -> Option<DateTime<FixedOffset>> {
+    let unit = match unit {
+        Some(unit) => unit,
+        None => EpochUnit::infer(n.as_f64()?)?,
+    };
+    // Integers are scaled exactly, so nanosecond timestamps keep their precision
+    let nanos = match n.as_i64() {
+        Some(i) => i128::from(i) * i128::from(unit.nanos()),
+        None => (n.as_f64()? * unit.nanos() as f64).round() as i128,
+    };
+    let secs = nanos.div_euclid(1_000_000_000);
+    let subsec = nanos.rem_euclid(1_000_000_000);
+    if secs > i128::from(i64::MAX) || secs < i128::from(i64::MIN) {
+        return None;
+    }
+    let d = DateTime::from_timestamp(secs as i64, subsec as u32)?;
+    Some(d.fixed_offset())
+}
+
+/// Checks that a strftime-style format string is valid, since formatting
+/// with an invalid one panics.
+pub fn validate_format(format: &str) -> Result<(), String> {
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        return Err(format!("invalid time format `{}`", format));
+    }
+    Ok(())
+}
+
+fn format_in<Z: TimeZone>(d: &DateTime<Z>, format: Option<&str>) -> String
+where
+    Z::Offset: ::std::fmt::Display,
+{
+    match format {
+        Some(format) => d.format(format).to_string(),
+        None => d.to_rfc3339_opts(SecondsFormat::Millis, true),
+    }
+}
+
+/// Renders a timestamp in the given zone (or its own offset) using a
+/// strftime-style format, defaulting to RFC 3339 with milliseconds.
+pub fn render(d: &DateTime<FixedOffset>, zone: Option<Zone>, format: Option<&str>) -> String {
+    match zone {
+        None => format_in(d, format),
+        Some(Zone::Local) => format_in(&d.with_timezone(&Local), format),
+        Some(Zone::Utc) => format_in(&d.with_timezone(&Utc), format),
+        Some(Zone::Named(tz)) => format_in(&d.with_timezone(&tz), format),
+    }
+}
+
+fn weekday(d: u32) -> Option<Weekday> {
+    match d {
+        1 => Some(Weekday::Mon),
+        2 => Some(Weekday::Tue),
+        3 => Some(Weekday::Wed),
+        4 => Some(Weekday::Thu),
+        5 => Some(Weekday::Fri),
+        6 => Some(Weekday::Sat),
+        7 => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+fn to_naive_date(date: iso8601::Date) -> Option<NaiveDate> {
+    match date {
+        iso8601::Date::YMD { year, month, day } => NaiveDate::from_ymd_opt(year, month, day),
+        iso8601::Date::Week { year, ww, d } => NaiveDate::from_isoywd_opt(year, ww, weekday(d)?),
+        iso8601::Date::Ordinal { year, ddd } => NaiveDate::from_yo_opt(year, ddd),
+    }
+}
+
+/// Converts the result of `iso8601::datetime` into a chrono timestamp.
+pub fn to_datetime(d: &iso8601::DateTime) -> Option<DateTime<FixedOffset>> {
+    let date = to_naive_date(d.date)?;
+    let time = NaiveTime::from_hms_milli_opt(
+        d.time.hour,
+        d.time.minute,
+        d.time.second,
+        d.time.millisecond,
+    )?;
+    let offset =
+        FixedOffset::east_opt(d.time.tz_offset_hours * 3600 + d.time.tz_offset_minutes * 60)?;
+    offset.from_local_datetime(&date.and_time(time)).single()
+}
+
+/// Parses an ISO 8601 timestamp, as found in log records.
+pub fn parse_timestamp(s: &str) -> Option<DateTime<FixedOffset>> {
+    match iso8601::datetime(s) {
+        Ok(d) => to_datetime(&d),
+        Err(_) => None,
+    }
+}
+
+/// Parses a relative duration like `15m`, `2h`, `1h30m` or `7d`. Returns
+/// `None` if `s` isn't a duration, and an error if it's too long to
+/// represent.
+fn parse_duration(s: &str) -> Option<Result<Duration, String>> {
+    if s.is_empty() {
+        return None;
+    }
+    let out_of_range = || Err(format!("duration `{}` is out of range", s));
+    let mut total = Some(Duration::zero());
+    let mut digits = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        if digits.is_empty() {
+            return None;
+        }
+        let n: Option<i64> = digits.parse().ok();
+        digits.clear();
+        let part = match c {
+            's' => n.and_then(Duration::try_seconds),
+            'm' => n.and_then(Duration::try_minutes),
+            'h' => n.and_then(Duration::try_hours),
+            'd' => n.and_then(Duration::try_days),
+            'w' => n.and_then(Duration::try_weeks),
+            _ => return None,
+        };
+        total = total.and_then(|total| total.checked_add(&part?));
+    }
+    if !digits.is_empty() {
+        return None;
+    }
+    Some(total.map_or_else(out_of_range, Ok))
+}
+
+/// Parses a `--since`/`--until` bound: `now`, a duration before `now` like
+/// `15m`, an ISO 8601 timestamp, or a date (taken as midnight UTC).
+pub fn parse_bound(s: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    let s = s.trim();
+    if s == "now" {
+        return Ok(now);
+    }
+    if let Some(duration) = parse_duration(s) {
+        return now
+            .checked_sub_signed(duration?)
+            .ok_or_else(|| format!("`{}` ago is out of range", s));
+    }
+    if let Some(d) = parse_timestamp(s) {
+        return Ok(d.with_timezone(&Utc));
+    }
+    if let Ok(date) = iso8601::date(s) {
+        if let Some(d) = to_naive_date(date) {
+            return Ok(Utc.from_utc_datetime(&d.and_time(NaiveTime::MIN)));
+        }
+    }
+    Err(format!(
+        "invalid time `{}`, expected an ISO 8601 timestamp or a duration like 15m",
+        s
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        from_epoch, parse_bound, parse_timestamp, render, validate_format, EpochUnit, Zone,
+    };
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn timestamp_utc() {
+        let d = parse_timestamp("2018-01-29T00:50:43.176Z").unwrap();
+        assert_eq!(d.to_rfc3339(), "2018-01-29T00:50:43.176+00:00");
+    }
+
+    #[test]
+    fn timestamp_with_offset() {
+        let d = parse_timestamp("2018-01-29T00:50:43-05:30").unwrap();
+        assert_eq!(d.to_rfc3339(), "2018-01-29T00:50:43-05:30");
+        assert_eq!(
+            d.with_timezone(&Utc).to_rfc3339(),
+            "2018-01-29T06:20:43+00:00"
+        );
+    }
+
+    #[test]
+    fn timestamp_invalid() {
+        assert_eq!(parse_timestamp("yesterday"), None);
+        assert_eq!(parse_timestamp("2018-02-30T00:00:00Z"), None);
+    }
+
+    #[test]
+    fn bound_relative() {
+        let now = Utc.with_ymd_and_hms(2018, 1, 29, 12, 0, 0).unwrap();
+        assert_eq!(parse_bound("now", now).unwrap(), now);
+        assert_eq!(
+            parse_bound("15m", now).unwrap(),
+            Utc.with_ymd_and_hms(2018, 1, 29, 11, 45, 0).unwrap()
+        );
+        assert_eq!(
+            parse_bound("1h30m", now).unwrap(),
+            Utc.with_ymd_and_hms(2018, 1, 29, 10, 30, 0).unwrap()
+        );
+        assert_eq!(
+            parse_bound("2d", now).unwrap(),
+            Utc.with_ymd_and_hms(2018, 1, 27, 12, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn bound_absolute() {
+        let now = Utc::now();
+        assert_eq!(
+            parse_bound("2018-01-29T00:50:43+01:00", now).unwrap(),
+            Utc.with_ymd_and_hms(2018, 1, 28, 23, 50, 43).unwrap()
+        );
+        assert_eq!(
+            parse_bound("2018-01-29", now).unwrap(),
+            Utc.with_ymd_and_hms(2018, 1, 29, 0, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn bound_invalid() {
+        let now = Utc::now();
+        assert!(parse_bound("15x", now).is_err());
+        assert!(parse_bound("m", now).is_err());
+        assert!(parse_bound("soon", now).is_err());
+        for huge in &[
+            "99999999999999w",
+            "99999999999999999999s",
+            "200000000w",
+            "1w9223372036854775807s",
+        ] {
+            let err = parse_bound(huge, now).unwrap_err();
+            assert!(err.contains("out of range"), "{}: {}", huge, err);
+        }
+    }
+
+    #[test]
+    fn zone_from_str() {
+        assert_eq!("local".parse::<Zone>(), Ok(Zone::Local));
+        assert_eq!("UTC".parse::<Zone>(), Ok(Zone::Utc));
+        assert_eq!(
+            "America/New_York".parse::<Zone>(),
+            Ok(Zone::Named(::chrono_tz::America::New_York))
+        );
+        assert!("Mars/Olympus_Mons".parse::<Zone>().is_err());
+    }
+
+    #[test]
+    fn render_default_format() {
+        let d = parse_timestamp("2018-01-29T00:50:43.176+01:00").unwrap();
+        assert_eq!(render(&d, None, None), "2018-01-29T00:50:43.176+01:00");
+        assert_eq!(
+            render(&d, Some(Zone::Utc), None),
+            "2018-01-28T23:50:43.176Z"
+        );
+    }
+
+    #[test]
+    fn render_named_zone() {
+        let d = parse_timestamp("2018-01-29T00:50:43.176Z").unwrap();
+        let zone = "America/New_York".parse::<Zone>().unwrap();
+        assert_eq!(
+            render(&d, Some(zone), None),
+            "2018-01-28T19:50:43.176-05:00"
+        );
+        assert_eq!(
+            render(&d, Some(zone), Some("%H:%M:%S%.3f %Z")),
+            "19:50:43.176 EST"
+        );
+    }
+
+    #[test]
+    fn render_custom_format() {
+        let d = parse_timestamp("2018-01-29T00:50:43.176Z").unwrap();
+        assert_eq!(render(&d, None, Some("%H:%M:%S%.3f")), "00:50:43.176");
+    }
+
+    #[test]
+    fn format_validation() {
+        assert!(validate_format("%H:%M:%S%.3f").is_ok());
+        assert!(validate_format("%Q").is_err());
+    }
+
+    fn epoch(json: &str, unit: Option<EpochUnit>) -> Option<String> {
+        let n: ::serde_json::Number = ::serde_json::from_str(json).unwrap();
+        from_epoch(&n, unit).map(|d| render(&d, None, Some("%Y-%m-%dT%H:%M:%S%.9f")))
+    }
+
+    #[test]
+    fn epoch_inferred_units() {
+        assert_eq!(
+            epoch("1706489443", None).unwrap(),
+            "2024-01-29T00:50:43.000000000"
+        );
+        assert_eq!(
+            epoch("1706489443.176", None).unwrap(),
+            "2024-01-29T00:50:43.176000000"
+        );
+        assert_eq!(
+            epoch("1706489443176", None).unwrap(),
+            "2024-01-29T00:50:43.176000000"
+        );
+        assert_eq!(
+            epoch("1706489443176123", None).unwrap(),
+            "2024-01-29T00:50:43.176123000"
+        );
+        assert_eq!(
+            epoch("1706489443176123456", None).unwrap(),
+            "2024-01-29T00:50:43.176123456"
+        );
+    }
+
+    #[test]
+    fn epoch_too_small_to_infer() {
+        assert_eq!(epoch("42", None), None);
+        assert_eq!(epoch("0.5", None), None);
+    }
+
+    #[test]
+    fn epoch_explicit_unit() {
+        assert_eq!(
+            epoch("42", Some(EpochUnit::Seconds)).unwrap(),
+            "1970-01-01T00:00:42.000000000"
+        );
+        assert_eq!(
+            epoch("1706489443", Some(EpochUnit::Millis)).unwrap(),
+            "1970-01-20T18:01:29.443000000"
+        );
+    }
+
+    #[test]
+    fn epoch_unit_from_str() {
+        assert_eq!("ms".parse::<EpochUnit>(), Ok(EpochUnit::Millis));
+        assert_eq!("us".parse::<EpochUnit>(), Ok(EpochUnit::Micros));
+        assert!("minutes".parse::<EpochUnit>().is_err());
+    }
+}